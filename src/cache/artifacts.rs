@@ -0,0 +1,445 @@
+use crate::config::Settings;
+use crate::exec::Executor;
+use crate::lang::{copy_artifacts, BuildStep, Diagnostic, LanguageExecutor, Languages};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Diagnostics of the original build, kept beside an entry's artifacts
+const DIAGNOSTICS_FILE: &str = ".diagnostics.json";
+
+struct Entry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    size: u64,
+    /// Ticks on every use, orders entries from least to most recently used
+    clock: u64,
+}
+
+/// Compiled artifacts, addressed by a hash of everything which went into
+/// building them. Least recently used entries are evicted once the cache
+/// grows beyond its maximum size.
+pub struct BuildCache {
+    directory: PathBuf,
+    max_size: u64,
+    index: Mutex<Index>,
+    /// Version of each language's toolchain, builds of those missing one aren't cached
+    toolchains: HashMap<Languages, String>,
+}
+
+impl BuildCache {
+    /// Opens the cache directory, indexing the entries left by earlier runs
+    pub fn open(settings: &Settings) -> Self {
+        let directory = PathBuf::from(&settings.cache_directory);
+        let mut index = Index::default();
+        let mut toolchains = HashMap::new();
+
+        if settings.cache_size > 0 {
+            if let Err(err) = fs::create_dir_all(&directory) {
+                println!("[CACHE]: Failed to create cache directory, {}", err);
+            }
+
+            let mut found = vec![];
+            for entry in fs::read_dir(&directory).into_iter().flatten().flatten() {
+                let path = entry.path();
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                // Partially written entries from an interrupted store
+                if name.starts_with('.') {
+                    let _ = fs::remove_dir_all(&path);
+                    continue;
+                }
+
+                let modified = entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                found.push((modified, name, size_of(&path)));
+            }
+
+            found.sort();
+            for (_, name, size) in found {
+                index.clock += 1;
+                index.size += size;
+                index.entries.insert(
+                    name,
+                    Entry {
+                        size,
+                        last_used: index.clock,
+                    },
+                );
+            }
+
+            println!(
+                "[CACHE]: Opened with {} entries, {} bytes",
+                index.entries.len(),
+                index.size
+            );
+
+            toolchains = Languages::ALL
+                .into_iter()
+                .filter_map(|language| Some((language, toolchain(settings, language)?)))
+                .collect();
+        }
+
+        BuildCache {
+            directory,
+            max_size: settings.cache_size,
+            index: Mutex::new(index),
+            toolchains,
+        }
+    }
+
+    /// Key for the artifacts `steps` would produce, if they can be cached
+    pub fn key(
+        &self,
+        exec: &Executor,
+        executor: &LanguageExecutor,
+        steps: &[BuildStep],
+    ) -> Option<String> {
+        if self.max_size == 0 || executor.artifacts.is_empty() {
+            return None;
+        }
+
+        let toolchain = self.toolchains.get(&exec.language)?;
+
+        let mut hasher = Sha256::new();
+        let mut update = |value: &[u8]| {
+            hasher.update(value);
+            hasher.update([0]);
+        };
+
+        update(env!("CARGO_PKG_VERSION").as_bytes());
+        update(exec.language.as_string().as_bytes());
+        update(toolchain.as_bytes());
+
+        for step in steps {
+            update(step.command.get_program().to_string_lossy().as_bytes());
+            for argument in step.command.get_args() {
+                update(argument.to_string_lossy().as_bytes());
+            }
+        }
+
+        for (name, version) in &exec.dependencies.packages {
+            update(name.as_bytes());
+            update(version.as_bytes());
+        }
+
+        update(exec.entrypoint.as_bytes());
+        for (path, contents) in &exec.files {
+            update(path.as_bytes());
+            update(contents);
+        }
+
+        Some(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Copies a cached entry's artifacts into `directory`, returning the
+    /// diagnostics of the build which produced them. `None` on a miss.
+    pub fn restore(
+        &self,
+        key: &str,
+        directory: &Path,
+        artifacts: &[&str],
+    ) -> Option<Vec<Diagnostic>> {
+        {
+            let mut index = self.index.lock().unwrap();
+            index.clock += 1;
+
+            let clock = index.clock;
+            index.entries.get_mut(key)?.last_used = clock;
+        }
+
+        let entry = self.directory.join(key);
+        let restored = copy_artifacts(&entry, directory, artifacts)
+            .and_then(|_| fs::read(entry.join(DIAGNOSTICS_FILE)));
+
+        match restored {
+            Ok(diagnostics) => Some(serde_json::from_slice(&diagnostics).unwrap_or_default()),
+            Err(err) => {
+                // Evicted while copying, or removed from underneath us
+                println!("[CACHE]: Failed to restore {}, {}", key, err);
+                None
+            }
+        }
+    }
+
+    /// Stores the artifacts built in `directory`, evicting older entries
+    /// until the cache fits within its maximum size again.
+    pub fn store(
+        &self,
+        key: &str,
+        directory: &Path,
+        artifacts: &[&str],
+        diagnostics: &[Diagnostic],
+    ) {
+        let staging = self.directory.join(format!(".{}-{}", key, Uuid::new_v4()));
+        let entry = self.directory.join(key);
+
+        let written = copy_artifacts(directory, &staging, artifacts)
+            .and_then(|_| {
+                let diagnostics = serde_json::to_vec(diagnostics).unwrap_or_default();
+                fs::write(staging.join(DIAGNOSTICS_FILE), diagnostics)
+            })
+            // Identical jobs may race to store the same entry, the first one wins
+            .and_then(|_| fs::rename(&staging, &entry));
+
+        if let Err(err) = written {
+            println!("[CACHE]: Failed to store {}, {}", key, err);
+            let _ = fs::remove_dir_all(&staging);
+            return;
+        }
+
+        let size = size_of(&entry);
+        let mut index = self.index.lock().unwrap();
+        index.clock += 1;
+
+        let clock = index.clock;
+        if let Some(previous) = index.entries.insert(
+            key.to_string(),
+            Entry {
+                size,
+                last_used: clock,
+            },
+        ) {
+            index.size -= previous.size;
+        }
+        index.size += size;
+
+        while index.size > self.max_size {
+            let Some(oldest) = index
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            if let Some(evicted) = index.entries.remove(&oldest) {
+                index.size -= evicted.size;
+                let _ = fs::remove_dir_all(self.directory.join(&oldest));
+                println!("[CACHE]: Evicted {}, {} bytes", oldest, evicted.size);
+            }
+        }
+    }
+}
+
+/// Version of `language`'s toolchain, as found on the `PATH` of its jobs
+fn toolchain(settings: &Settings, language: Languages) -> Option<String> {
+    let (program, arguments) = language.executor()?.toolchain.split_first()?;
+    let output = Command::new(program)
+        .args(arguments)
+        .env_clear()
+        .envs(settings.environment(language))
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).to_string())
+        }
+        _ => {
+            println!(
+                "[CACHE]: No toolchain found for {}, its builds are not cached",
+                language.as_string()
+            );
+            None
+        }
+    }
+}
+
+fn size_of(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| size_of(&entry.path()))
+            .sum(),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{toolchain, BuildCache, Index};
+    use crate::config::Settings;
+    use crate::exec::{Dependencies, Environment, Executor, ExecutorBuilder, Limits, OutputLimits};
+    use crate::lang::{BuildStep, Diagnostic, DiagnosticFormat, Languages, Severity};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    const ARTIFACTS: &[&str] = &["exec.out"];
+
+    fn cache(max_size: u64) -> BuildCache {
+        BuildCache {
+            directory: std::env::temp_dir().join(format!("roadrunner-cache-{}", Uuid::new_v4())),
+            max_size,
+            index: Mutex::new(Index::default()),
+            toolchains: HashMap::from([(Languages::C, "gcc 12.2.0".to_string())]),
+        }
+    }
+
+    fn job(files: &[(&str, &str)]) -> ExecutorBuilder {
+        ExecutorBuilder::new()
+            .language(Languages::C)
+            .files(
+                files
+                    .iter()
+                    .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                    .collect(),
+            )
+            .environment(Environment::default())
+            .limits(Limits {
+                time_limit: Duration::from_secs(1),
+                compile_time_limit: Duration::from_secs(1),
+                idle_timeout: None,
+                kill_grace_period: Duration::from_secs(1),
+                archive_size: 0,
+                archive_files: 0,
+                artifact_size: 0,
+                artifact_files: 0,
+                run_resources: Default::default(),
+                compile_resources: Default::default(),
+                output: OutputLimits::default(),
+            })
+    }
+
+    fn key(cache: &BuildCache, exec: &Executor, flags: &[&str]) -> Option<String> {
+        let executor = exec.language.executor().unwrap();
+        let mut compiler = Command::new("gcc");
+        compiler.args(flags);
+
+        let steps = [BuildStep::new(compiler, DiagnosticFormat::Positional)];
+        cache.key(exec, executor, &steps)
+    }
+
+    /// A job directory holding a built `exec.out` of `contents`
+    fn built(cache: &BuildCache, contents: &str) -> PathBuf {
+        let directory = cache.directory.join(format!(".job-{}", Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("exec.out"), contents).unwrap();
+        directory
+    }
+
+    fn restored(cache: &BuildCache, key: &str) -> Option<String> {
+        let directory = built(cache, "");
+        cache.restore(key, &directory, ARTIFACTS)?;
+        fs::read_to_string(Path::new(&directory).join("exec.out")).ok()
+    }
+
+    #[test]
+    fn keys_are_stable() {
+        let cache = cache(1 << 20);
+        let files = [("main.c", "int main() {}"), ("util.h", "")];
+
+        let first = key(&cache, &job(&files).build(Uuid::nil()), &["-O2"]);
+        let second = key(&cache, &job(&files).build(Uuid::new_v4()), &["-O2"]);
+
+        assert!(first.is_some());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn keys_change_with_the_build() {
+        let cache = cache(1 << 20);
+        let files = [("main.c", "int main() {}")];
+        let original = key(&cache, &job(&files).build(Uuid::nil()), &["-O2"]);
+
+        let changed = [
+            job(&[("main.c", "int main() { return 1; }")]),
+            job(&[("src/main.c", "int main() {}")]),
+            job(&[("main.c", "int main() {}"), ("util.h", "")]),
+            job(&files).entrypoint(Some("util.c".to_string())),
+            job(&files).dependencies(Dependencies {
+                packages: BTreeMap::from([("zlib".to_string(), "1.3".to_string())]),
+                mirror: None,
+            }),
+        ];
+        for job in changed {
+            assert_ne!(key(&cache, &job.build(Uuid::nil()), &["-O2"]), original);
+        }
+
+        let exec = job(&files).build(Uuid::nil());
+        assert_ne!(key(&cache, &exec, &["-O0"]), original);
+        assert_ne!(key(&cache, &exec, &["-O2", "-g"]), original);
+    }
+
+    #[test]
+    fn keys_need_a_toolchain() {
+        let cache = cache(1 << 20);
+        let exec = job(&[]).language(Languages::Cpp).build(Uuid::nil());
+
+        assert!(key(&cache, &exec, &[]).is_none());
+    }
+
+    #[test]
+    fn finds_toolchains_on_the_jobs_path() {
+        let settings = Settings::from_env();
+        assert!(toolchain(&settings, Languages::C).is_some());
+
+        let settings = Settings {
+            language_path: HashMap::from([(Languages::C, "/nonexistent".to_string())]),
+            ..settings
+        };
+        assert!(toolchain(&settings, Languages::C).is_none());
+    }
+
+    #[test]
+    fn restores_what_was_stored() {
+        let cache = cache(1 << 20);
+        let diagnostics = [Diagnostic {
+            file: "main.c".to_string(),
+            line: 2,
+            column: 5,
+            severity: Severity::Warning,
+            message: "unused variable 'total'".to_string(),
+        }];
+
+        assert!(restored(&cache, "built").is_none());
+
+        cache.store("built", &built(&cache, "binary"), ARTIFACTS, &diagnostics);
+        assert_eq!(restored(&cache, "built").as_deref(), Some("binary"));
+
+        let directory = built(&cache, "");
+        let restored = cache.restore("built", &directory, ARTIFACTS).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].message, diagnostics[0].message);
+
+        fs::remove_dir_all(&cache.directory).unwrap();
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        // Each entry is its 4 byte artifact and 2 bytes of diagnostics, `[]`
+        let cache = cache(12);
+
+        cache.store("first", &built(&cache, "1111"), ARTIFACTS, &[]);
+        cache.store("second", &built(&cache, "2222"), ARTIFACTS, &[]);
+        assert!(restored(&cache, "first").is_some());
+
+        cache.store("third", &built(&cache, "3333"), ARTIFACTS, &[]);
+        assert!(restored(&cache, "second").is_none());
+        assert!(!cache.directory.join("second").exists());
+        assert_eq!(restored(&cache, "first").as_deref(), Some("1111"));
+        assert_eq!(restored(&cache, "third").as_deref(), Some("3333"));
+
+        fs::remove_dir_all(&cache.directory).unwrap();
+    }
+}
@@ -0,0 +1,3 @@
+pub use artifacts::*;
+
+mod artifacts;
@@ -0,0 +1,244 @@
+use crate::config::Settings;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Controllers whose limits are applied to each job, where the host delegates them
+const CONTROLLERS: [&str; 3] = ["memory", "cpu", "pids"];
+/// Period `cpu.max` quotas are given over, in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+/// Measurements of a job's cgroup, taken once its program has exited
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    /// Bytes, needs `memory.peak`
+    pub memory_peak: Option<u64>,
+    pub cpu_time: Option<Duration>,
+    /// Whether the kernel killed a process for exceeding `memory.max`
+    pub oom_killed: bool,
+}
+
+/// A cgroup v2 subtree holding one cgroup per running job. Should the host
+/// lack a writable cgroup v2 hierarchy, jobs simply run without one.
+pub struct Cgroups {
+    directory: Option<PathBuf>,
+    controllers: Vec<String>,
+    memory: Option<u64>,
+    cpus: Option<f64>,
+    pids: Option<u64>,
+}
+
+/// A job's own cgroup, killed and removed once dropped
+pub struct Cgroup {
+    path: PathBuf,
+    procs: File,
+}
+
+impl Cgroups {
+    pub fn open(settings: &Settings) -> Self {
+        let mut cgroups = Cgroups {
+            directory: None,
+            controllers: vec![],
+            memory: settings.cgroup_memory,
+            cpus: settings.cgroup_cpus,
+            pids: settings.cgroup_pids,
+        };
+
+        if !settings.cgroups {
+            return cgroups;
+        }
+
+        let directory = match &settings.cgroup_directory {
+            Some(directory) => PathBuf::from(directory),
+            None => match own_cgroup() {
+                Some((mount, path)) => {
+                    let directory = match path.trim_start_matches('/') {
+                        "" => mount.clone(),
+                        path => mount.join(path),
+                    };
+
+                    // Only a cgroup without processes of its own may delegate
+                    // controllers, so the server steps aside into a leaf.
+                    if directory != mount {
+                        let server = directory.join("server");
+                        let moved = fs::create_dir_all(&server).and_then(|_| {
+                            fs::write(server.join("cgroup.procs"), std::process::id().to_string())
+                        });
+
+                        if let Err(err) = moved {
+                            println!("[CGROUP]: Could not move the server into a leaf, {}", err);
+                        }
+                    }
+
+                    directory
+                }
+                None => {
+                    println!("[CGROUP]: No cgroup v2 hierarchy found, jobs run without one");
+                    return cgroups;
+                }
+            },
+        };
+
+        let available =
+            fs::read_to_string(directory.join("cgroup.controllers")).unwrap_or_default();
+        for controller in CONTROLLERS {
+            if available.split_whitespace().any(|name| name == controller) {
+                let _ = fs::write(
+                    directory.join("cgroup.subtree_control"),
+                    format!("+{}", controller),
+                );
+            }
+        }
+
+        // The hierarchy may be mounted, but not ours to write to
+        let probe = directory.join(format!("probe-{}", Uuid::new_v4()));
+        if let Err(err) = fs::create_dir(&probe).and_then(|_| fs::remove_dir(&probe)) {
+            println!(
+                "[CGROUP]: {} is not writable, jobs run without a cgroup, {}",
+                directory.display(),
+                err
+            );
+            return cgroups;
+        }
+
+        cgroups.controllers = fs::read_to_string(directory.join("cgroup.subtree_control"))
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        println!(
+            "[CGROUP]: Jobs run in {}, with controllers {:?}",
+            directory.display(),
+            cgroups.controllers
+        );
+
+        cgroups.directory = Some(directory);
+        cgroups
+    }
+
+    /// Creates the job's cgroup with whichever limits the host supports,
+    /// `None` if cgroups are unavailable.
+    pub fn create(&self, id: Uuid) -> Option<Cgroup> {
+        let path = self.directory.as_ref()?.join(format!("job-{}", id));
+
+        if let Err(err) = fs::create_dir(&path) {
+            println!("[CGROUP]: Failed to create {}, {}", path.display(), err);
+            return None;
+        }
+
+        let enabled = |controller: &str| self.controllers.iter().any(|name| name == controller);
+        let mut limits = vec![];
+
+        if let (true, Some(memory)) = (enabled("memory"), self.memory) {
+            limits.push(("memory.max", memory.to_string()));
+            // Reaching the limit should kill, rather than swap
+            limits.push(("memory.swap.max", "0".to_string()));
+        }
+        if let (true, Some(cpus)) = (enabled("cpu"), self.cpus) {
+            let quota = (cpus * CPU_PERIOD as f64).max(1000.0) as u64;
+            limits.push(("cpu.max", format!("{} {}", quota, CPU_PERIOD)));
+        }
+        if let (true, Some(pids)) = (enabled("pids"), self.pids) {
+            limits.push(("pids.max", pids.to_string()));
+        }
+
+        for (file, value) in limits {
+            if let Err(err) = fs::write(path.join(file), value) {
+                println!("[CGROUP]: Failed to set {} for {}, {}", file, id, err);
+            }
+        }
+
+        match OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"))
+        {
+            Ok(procs) => Some(Cgroup { path, procs }),
+            Err(err) => {
+                println!("[CGROUP]: Failed to open {}, {}", path.display(), err);
+                let _ = fs::remove_dir(&path);
+                None
+            }
+        }
+    }
+}
+
+impl Cgroup {
+    /// Moves `command`'s child into the cgroup before it executes, so that
+    /// everything it starts is accounted for too.
+    pub fn enter(&self, command: &mut Command) {
+        let procs = self.procs.as_raw_fd();
+
+        // SAFETY: only `write` is called, which is async-signal-safe. Writing
+        // `0` to `cgroup.procs` moves the writing process.
+        unsafe {
+            command.pre_exec(move || {
+                match libc::write(procs, b"0".as_ptr() as *const libc::c_void, 1) {
+                    1 => Ok(()),
+                    _ => Err(std::io::Error::last_os_error()),
+                }
+            });
+        }
+    }
+
+    pub fn usage(&self) -> Usage {
+        let read = |file: &str| fs::read_to_string(self.path.join(file)).unwrap_or_default();
+
+        Usage {
+            memory_peak: read("memory.peak").trim().parse().ok(),
+            cpu_time: field(&read("cpu.stat"), "usage_usec").map(Duration::from_micros),
+            oom_killed: field(&read("memory.events"), "oom_kill").is_some_and(|count| count > 0),
+        }
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // Anything the job left running goes with it
+        let _ = fs::write(self.path.join("cgroup.kill"), "1");
+
+        if fs::remove_dir(&self.path).is_err() {
+            // Killed processes take a moment to leave
+            let path = self.path.clone();
+            std::thread::spawn(move || {
+                for _ in 0..50 {
+                    std::thread::sleep(Duration::from_millis(100));
+                    if fs::remove_dir(&path).is_ok() {
+                        return;
+                    }
+                }
+
+                println!("[CGROUP]: Failed to remove {}", path.display());
+            });
+        }
+    }
+}
+
+/// Value of `key` in a flat-keyed file such as `cpu.stat`
+fn field(contents: &str, key: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok()).flatten()
+    })
+}
+
+/// Mount point of the cgroup v2 hierarchy, and the server's cgroup within it
+fn own_cgroup() -> Option<(PathBuf, String)> {
+    let mounts = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let mount = mounts.lines().find_map(|line| {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        match filesystem.split_whitespace().next()? {
+            "cgroup2" => mount.split_whitespace().nth(4).map(Path::new),
+            _ => None,
+        }
+    })?;
+
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+    Some((mount.to_path_buf(), path.to_string()))
+}
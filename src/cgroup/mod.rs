@@ -0,0 +1,3 @@
+pub use hierarchy::*;
+
+mod hierarchy;
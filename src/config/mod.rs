@@ -0,0 +1,3 @@
+pub use settings::*;
+
+mod settings;
@@ -0,0 +1,298 @@
+use crate::exec::{OutputLimits, OutputPolicy, ResourceLimits};
+use crate::lang::Languages;
+use crate::sandbox::{Backend, Sandbox, Seccomp};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::available_parallelism;
+use std::time::Duration;
+
+/// Server-side configuration, read from the environment (or `.env`) on startup.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Time limit applied when a request does not provide one
+    pub default_time_limit: Duration,
+    /// Upper bound for any requested time limit
+    pub max_time_limit: Duration,
+    /// Time allowed for all of a job's build steps together
+    pub compile_time_limit: Duration,
+    /// Time between `SIGTERM` and `SIGKILL` once a limit is reached
+    pub kill_grace_period: Duration,
+    /// Kill jobs which produce no output for this long
+    pub idle_timeout: Option<Duration>,
+    /// Jobs which may execute at once, the rest wait in the queue
+    pub max_concurrent_jobs: usize,
+    /// Tighter per-language bounds, e.g. `MAX_CONCURRENT_RUST=2`
+    pub language_concurrency: HashMap<Languages, usize>,
+    /// Where the warm workspaces of languages with a template are kept
+    pub template_directory: String,
+    /// Workspaces per language with a template, `0` builds every job from scratch
+    pub template_slots: usize,
+    /// Local package mirror, with a directory per package manager. Dependencies are refused without one.
+    pub dependency_mirror: Option<String>,
+    /// Where compiled artifacts are cached
+    pub cache_directory: String,
+    /// Bytes the build cache may hold before evicting, `0` disables it
+    pub cache_size: u64,
+    /// Bytes an uploaded archive may unpack to
+    pub archive_size: u64,
+    /// Files an uploaded archive may hold
+    pub archive_files: usize,
+    /// Bytes of artifacts returned from a job, files beyond it are omitted
+    pub artifact_size: u64,
+    /// Artifacts returned from a job, files beyond it are omitted
+    pub artifact_files: usize,
+    /// Upper bounds for a job's program, requests may only tighten them.
+    /// See [`Settings::run_resources`] for those of a language.
+    pub run_resources: ResourceLimits,
+    /// Applied to each build step
+    pub compile_resources: ResourceLimits,
+    /// Caps on a program's output, and what happens once one is reached
+    pub output_limits: OutputLimits,
+    /// Whether jobs are placed in a cgroup of their own, where the host allows it
+    pub cgroups: bool,
+    /// Delegated cgroup v2 directory for jobs, the server's own cgroup if unset
+    pub cgroup_directory: Option<String>,
+    /// `memory.max` of each job, in bytes
+    pub cgroup_memory: Option<u64>,
+    /// `cpu.max` of each job, in CPUs
+    pub cgroup_cpus: Option<f64>,
+    /// `pids.max` of each job
+    pub cgroup_pids: Option<u64>,
+    /// Confinement of every process a job starts
+    pub sandbox: Sandbox,
+    /// Variables every job starts with, none of the server's others are inherited
+    pub environment: BTreeMap<String, String>,
+    /// `PATH` of each language's jobs where it differs, e.g. `JOB_PATH_PYTHON`
+    pub language_path: HashMap<Languages, String>,
+    /// Address space of each language's programs, e.g. `RLIMIT_AS_BYTES_GO`
+    pub language_address_space: HashMap<Languages, Option<u64>>,
+}
+
+impl Settings {
+    pub fn from_env() -> Self {
+        let max_concurrent_jobs = var("MAX_CONCURRENT_JOBS")
+            .unwrap_or_else(|| available_parallelism().map_or(1, |count| count.get()))
+            .max(1);
+
+        let dependency_mirror = var::<String>("DEPENDENCY_MIRROR_DIR").map(absolute);
+        let run_resources = resources(
+            "RLIMIT",
+            ResourceLimits {
+                address_space: Some(1 << 30),
+                cpu_time: None,
+                // Matches `CGROUP_PIDS_MAX`, though counted across the sandbox's user
+                processes: Some(128),
+                open_files: Some(256),
+                file_size: Some(64 << 20),
+                core_size: Some(0),
+            },
+        );
+
+        Settings {
+            default_time_limit: millis("DEFAULT_TIME_LIMIT_MS").unwrap_or(Duration::from_secs(5)),
+            max_time_limit: millis("MAX_TIME_LIMIT_MS").unwrap_or(Duration::from_secs(30)),
+            compile_time_limit: millis("COMPILE_TIME_LIMIT_MS").unwrap_or(Duration::from_secs(30)),
+            kill_grace_period: millis("KILL_GRACE_PERIOD_MS").unwrap_or(Duration::from_secs(1)),
+            idle_timeout: millis("IDLE_TIMEOUT_MS"),
+            max_concurrent_jobs,
+            language_concurrency: Languages::ALL
+                .into_iter()
+                .filter_map(|language| {
+                    let key = format!("MAX_CONCURRENT_{}", language.as_string().to_uppercase());
+                    var(&key).map(|limit: usize| (language, limit.max(1)))
+                })
+                .collect(),
+            template_directory: var("TEMPLATE_DIR").unwrap_or_else(|| "templates".to_string()),
+            template_slots: var("TEMPLATE_SLOTS").unwrap_or(max_concurrent_jobs),
+            dependency_mirror: dependency_mirror.clone(),
+            cache_directory: var("BUILD_CACHE_DIR").unwrap_or_else(|| "cache".to_string()),
+            cache_size: var("BUILD_CACHE_MAX_BYTES").unwrap_or(1 << 30),
+            archive_size: var("ARCHIVE_MAX_BYTES").unwrap_or(64 << 20),
+            archive_files: var("ARCHIVE_MAX_FILES").unwrap_or(1000),
+            artifact_size: var("ARTIFACT_MAX_BYTES").unwrap_or(8 << 20),
+            artifact_files: var("ARTIFACT_MAX_FILES").unwrap_or(32),
+            run_resources,
+            compile_resources: resources(
+                "COMPILE_RLIMIT",
+                ResourceLimits {
+                    address_space: None,
+                    cpu_time: None,
+                    processes: Some(4096),
+                    open_files: Some(4096),
+                    file_size: Some(1 << 30),
+                    core_size: Some(0),
+                },
+            ),
+            output_limits: output_limits(),
+            cgroups: var("CGROUPS").unwrap_or(true),
+            cgroup_directory: var("CGROUP_DIR"),
+            cgroup_memory: limit("CGROUP_MEMORY_MAX_BYTES", Some(512 << 20)),
+            cgroup_cpus: var("CGROUP_CPUS"),
+            cgroup_pids: limit("CGROUP_PIDS_MAX", Some(128)),
+            sandbox: sandbox(&dependency_mirror),
+            environment: environment(),
+            language_path: Languages::ALL
+                .into_iter()
+                .filter_map(|language| {
+                    let key = format!("JOB_PATH_{}", language.as_string().to_uppercase());
+                    var(&key).map(|path: String| (language, path))
+                })
+                .collect(),
+            language_address_space: Languages::ALL
+                .into_iter()
+                .map(|language| {
+                    // Their runtimes reserve far more address space than they use
+                    let default = match language {
+                        Languages::Go | Languages::Javascript => None,
+                        _ => run_resources.address_space,
+                    };
+
+                    let key = format!("RLIMIT_AS_BYTES_{}", language.as_string().to_uppercase());
+                    (language, limit(&key, default))
+                })
+                .collect(),
+        }
+    }
+
+    /// Variables each of `language`'s processes start with
+    pub fn environment(&self, language: Languages) -> BTreeMap<String, String> {
+        let mut environment = self.environment.clone();
+        if let Some(path) = self.language_path.get(&language) {
+            environment.insert("PATH".to_string(), path.clone());
+        }
+        environment
+    }
+
+    /// Upper bounds for a program of `language`
+    pub fn run_resources(&self, language: Languages) -> ResourceLimits {
+        ResourceLimits {
+            address_space: self
+                .language_address_space
+                .get(&language)
+                .copied()
+                .unwrap_or(self.run_resources.address_space),
+            ..self.run_resources
+        }
+    }
+}
+
+fn var<T: FromStr>(key: &str) -> Option<T> {
+    dotenv::var(key)
+        .ok()
+        .and_then(|value| value.parse::<T>().ok())
+}
+
+/// Resolves a relative path against the server's directory, as jobs run from their own
+fn absolute(path: String) -> String {
+    match std::env::current_dir() {
+        Ok(directory) => directory.join(path).to_string_lossy().to_string(),
+        Err(_) => path,
+    }
+}
+
+/// Reads `{prefix}_AS_BYTES`, `{prefix}_CPU_SECONDS`, ... over `defaults`
+fn resources(prefix: &str, defaults: ResourceLimits) -> ResourceLimits {
+    let limit = |name: &str, default: Option<u64>| limit(&format!("{}_{}", prefix, name), default);
+
+    ResourceLimits {
+        address_space: limit("AS_BYTES", defaults.address_space),
+        cpu_time: limit("CPU_SECONDS", defaults.cpu_time),
+        processes: limit("NPROC", defaults.processes),
+        open_files: limit("NOFILE", defaults.open_files),
+        file_size: limit("FSIZE_BYTES", defaults.file_size),
+        core_size: limit("CORE_BYTES", defaults.core_size),
+    }
+}
+
+/// Reads the `OUTPUT_MAX_*` caps of each stream, `JOB_OUTPUT_MAX_*` of both
+/// together, and `OUTPUT_POLICY`, one of `kill` or `discard`
+fn output_limits() -> OutputLimits {
+    let policy = var::<String>("OUTPUT_POLICY").unwrap_or_else(|| "kill".to_string());
+
+    OutputLimits {
+        stream_bytes: limit("OUTPUT_MAX_BYTES", Some(1 << 20)),
+        stream_lines: limit("OUTPUT_MAX_LINES", Some(10_000)),
+        job_bytes: limit("JOB_OUTPUT_MAX_BYTES", Some(2 << 20)),
+        job_lines: limit("JOB_OUTPUT_MAX_LINES", Some(20_000)),
+        policy: OutputPolicy::from_string(&policy)
+            .unwrap_or_else(|| panic!("[OUTPUT]: Unknown policy '{}'", policy)),
+    }
+}
+
+/// Reads `ENV_PASSTHROUGH`, the server's own variables which jobs keep, over
+/// a UTF-8 locale. `JOB_PATH` replaces the server's `PATH`.
+fn environment() -> BTreeMap<String, String> {
+    let passthrough = var::<String>("ENV_PASSTHROUGH").unwrap_or_else(|| {
+        "PATH,HOME,LANG,LC_ALL,TZ,RUSTUP_HOME,CARGO_HOME,GOROOT,GOPATH,GOBIN,BUN_INSTALL"
+            .to_string()
+    });
+
+    let mut environment = BTreeMap::from([("LANG".to_string(), "C.UTF-8".to_string())]);
+    for key in passthrough.split(',').map(str::trim) {
+        if let Ok(value) = dotenv::var(key) {
+            environment.insert(key.to_string(), value);
+        }
+    }
+
+    if let Some(path) = var("JOB_PATH") {
+        environment.insert("PATH".to_string(), path);
+    }
+
+    environment
+}
+
+/// Reads `SANDBOX`, one of `none`, `unshare` or `bwrap`, and its options.
+/// An unknown backend, or a profile which cannot be loaded, is refused
+/// rather than running jobs unconfined.
+fn sandbox(dependency_mirror: &Option<String>) -> Sandbox {
+    let backend = var::<String>("SANDBOX").unwrap_or_else(|| "none".to_string());
+    let backend = Backend::from_string(&backend)
+        .unwrap_or_else(|| panic!("[SANDBOX]: Unknown backend '{}'", backend));
+
+    let mut readonly: Vec<String> = var::<String>("SANDBOX_READONLY_PATHS")
+        .unwrap_or_else(|| "/usr,/bin,/sbin,/lib,/lib32,/lib64,/libx32,/etc".to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect();
+    readonly.extend(dependency_mirror.clone());
+
+    let network = var("SANDBOX_NETWORK").unwrap_or(false);
+    let seccomp = match var("SECCOMP").unwrap_or(false) {
+        true => {
+            let directory = var("SECCOMP_PROFILE_DIR").unwrap_or_else(|| "seccomp".to_string());
+            let seccomp = Seccomp::load(&directory, network)
+                .unwrap_or_else(|err| panic!("[SECCOMP]: {}", err));
+
+            // Programs are started through the server's binary
+            readonly.push(seccomp.supervisor.to_string_lossy().to_string());
+            Some(Arc::new(seccomp))
+        }
+        false => None,
+    };
+
+    Sandbox {
+        backend,
+        uid: var("SANDBOX_UID").unwrap_or(65534),
+        gid: var("SANDBOX_GID").unwrap_or(65534),
+        network,
+        readonly,
+        root: absolute(var("SANDBOX_ROOT").unwrap_or_else(|| "sandbox".to_string())),
+        seccomp,
+    }
+}
+
+/// A numeric limit, where `unlimited` lifts it altogether
+fn limit(key: &str, default: Option<u64>) -> Option<u64> {
+    match dotenv::var(key).as_deref() {
+        Ok("unlimited") => None,
+        Ok(value) => value.parse().ok().or(default),
+        Err(_) => default,
+    }
+}
+
+fn millis(key: &str) -> Option<Duration> {
+    var::<u64>(key).map(Duration::from_millis)
+}
@@ -1,45 +1,118 @@
-use crate::lang::{ExecutionOutput, Languages};
+use crate::lang::{ExecutionOutput, Languages, RuntimeError};
+use crate::sandbox::Sandbox;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use chrono::offset::Utc;
 use chrono::DateTime;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::Duration;
 use tokio::sync::broadcast;
 use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use uuid::Uuid;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Arguments {
     pub argument_count: i32,
     pub arguments: Vec<String>,
 }
 
+/// Arguments as requested, either one per element or as a single line
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CommandlineArguments {
+    List(Vec<String>),
+    /// Split by POSIX shell quoting rules, e.g. `-n "a b"` gives `-n` and `a b`
+    Line(String),
+}
+
 impl Arguments {
-    pub fn parse(argument_string: String) -> Self {
-        let arguments: Vec<String> = argument_string.split(' ').map(|e| e.to_string()).collect();
+    pub fn parse(arguments: CommandlineArguments) -> Result<Self, RuntimeError> {
+        let arguments = match arguments {
+            CommandlineArguments::List(arguments) => arguments,
+            CommandlineArguments::Line(line) => shell_words::split(&line).map_err(|err| {
+                RuntimeError::ParseInput(format!(
+                    "`commandline_arguments` could not be split, {}",
+                    err
+                ))
+            })?,
+        };
 
-        Arguments {
+        Ok(Arguments {
             argument_count: arguments.len().try_into().unwrap(),
             arguments,
-        }
+        })
     }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "terminal_type")]
 pub enum TerminalStreamType {
     StandardInput,
     StandardOutput,
     StandardError,
     EndOfOutput,
+
+    /// Waiting for a free slot, `position` 1 is next in line
+    Queued {
+        position: usize,
+    },
+    Compiling,
+    /// Output of a build step, compiler messages included
+    CompilerOutput,
+    Running,
+    Finished {
+        timings: Timing,
+    },
+    /// Output of `stream` reached `limit`, anything further is not sent
+    Truncated {
+        stream: OutputStream,
+        limit: OutputLimit,
+    },
+}
+
+/// One of the program's output streams
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum OutputStream {
+    StandardOutput,
+    StandardError,
 }
 
 #[derive(Clone, Serialize, Debug)]
 pub struct TerminalStream {
+    #[serde(flatten)]
     pub terminal_type: TerminalStreamType,
     pub value: Option<ExecutionOutput>,
     pub pipe_value: Option<String>,
+    /// Set when `pipe_value` is not the output itself, but encoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
     pub nonce: Option<String>,
     pub timestamp: DateTime<Utc>,
 }
 
+/// How output which is not valid UTF-8 is carried in `pipe_value`
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Base64,
+}
+
+/// How a program's output is divided into events
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    /// An event per line, without its newline
+    #[default]
+    Line,
+    /// Bytes as they arrive, newlines included
+    Chunk,
+}
+
 impl TerminalStream {
     /// Stream pipe feed (`stdin`, `stdout`, `stderr`)
     pub fn new(terminal_type: TerminalStreamType, value: String, nonce: Option<String>) -> Self {
@@ -47,6 +120,34 @@ impl TerminalStream {
             terminal_type,
             value: None,
             pipe_value: Some(value),
+            encoding: None,
+            nonce,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Stream pipe feed of raw output, base64 encoded unless it is UTF-8
+    pub fn new_bytes(
+        terminal_type: TerminalStreamType,
+        value: &[u8],
+        nonce: Option<String>,
+    ) -> Self {
+        match std::str::from_utf8(value) {
+            Ok(value) => TerminalStream::new(terminal_type, value.to_string(), nonce),
+            Err(_) => TerminalStream {
+                encoding: Some(Encoding::Base64),
+                ..TerminalStream::new(terminal_type, STANDARD.encode(value), nonce)
+            },
+        }
+    }
+
+    /// Stream lifecycle event, carrying no pipe or output value
+    pub fn new_event(terminal_type: TerminalStreamType, nonce: Option<String>) -> Self {
+        TerminalStream {
+            terminal_type,
+            value: None,
+            pipe_value: None,
+            encoding: None,
             nonce,
             timestamp: Utc::now(),
         }
@@ -62,6 +163,7 @@ impl TerminalStream {
             terminal_type,
             value: Some(value),
             pipe_value: None,
+            encoding: None,
             nonce,
             timestamp: Utc::now(),
         }
@@ -76,19 +178,410 @@ pub struct TerminalFeed {
     pub output: Vec<TerminalStream>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
 pub struct Timing {
     pub time_received: Option<DateTime<Utc>>,
     pub time_executed: Option<DateTime<Utc>>,
     pub time_completed: Option<DateTime<Utc>>,
 }
 
+/// Execution limits, already resolved against the server's maximums
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub time_limit: Duration,
+    pub compile_time_limit: Duration,
+    pub idle_timeout: Option<Duration>,
+    pub kill_grace_period: Duration,
+    /// Bytes an uploaded archive may unpack to
+    pub archive_size: u64,
+    /// Files an uploaded archive may hold
+    pub archive_files: usize,
+    /// Bytes of artifacts returned from a job
+    pub artifact_size: u64,
+    /// Artifacts returned from a job
+    pub artifact_files: usize,
+    /// Applied to the program and everything it starts
+    pub run_resources: ResourceLimits,
+    /// Applied to each build step
+    pub compile_resources: ResourceLimits,
+    /// Caps on what the program may write to `stdout` and `stderr`
+    pub output: OutputLimits,
+}
+
+/// What happens to a program once its output reaches a cap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputPolicy {
+    /// Kill the program, ending with `OutputLimitExceeded`
+    #[default]
+    Kill,
+    /// Let the program run on, discarding anything further it writes
+    Discard,
+}
+
+impl OutputPolicy {
+    pub fn from_string(policy: &str) -> Option<Self> {
+        match policy {
+            "kill" => Some(OutputPolicy::Kill),
+            "discard" => Some(OutputPolicy::Discard),
+            _ => None,
+        }
+    }
+}
+
+/// Caps on a program's output, `None` leaves it uncapped
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OutputLimits {
+    /// Bytes of `stdout` or `stderr`, each on its own. Counted as the program
+    /// wrote them, before any base64 encoding.
+    pub stream_bytes: Option<u64>,
+    pub stream_lines: Option<u64>,
+    /// Bytes of both streams together
+    pub job_bytes: Option<u64>,
+    pub job_lines: Option<u64>,
+    pub policy: OutputPolicy,
+}
+
+/// The cap a program's output reached
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OutputLimit {
+    StreamBytes,
+    StreamLines,
+    JobBytes,
+    JobLines,
+}
+
+/// Kernel resource limits, set with `setrlimit` in each child before it
+/// executes. `None` leaves the server's own limit in place.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Bytes of virtual memory
+    pub address_space: Option<u64>,
+    /// Seconds of CPU time, per process
+    pub cpu_time: Option<u64>,
+    /// Processes and threads of the server's user, not only the job's
+    pub processes: Option<u64>,
+    pub open_files: Option<u64>,
+    /// Bytes of any one file written
+    pub file_size: Option<u64>,
+    pub core_size: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// The tighter of each limit
+    pub fn min(self, other: ResourceLimits) -> Self {
+        let min = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        ResourceLimits {
+            address_space: min(self.address_space, other.address_space),
+            cpu_time: min(self.cpu_time, other.cpu_time),
+            processes: min(self.processes, other.processes),
+            open_files: min(self.open_files, other.open_files),
+            file_size: min(self.file_size, other.file_size),
+            core_size: min(self.core_size, other.core_size),
+        }
+    }
+
+    /// Sets the limits in `command`'s child, between `fork` and `exec`
+    pub fn apply(self, command: &mut Command) {
+        let limits = [
+            (libc::RLIMIT_AS, self.address_space),
+            (libc::RLIMIT_CPU, self.cpu_time),
+            (libc::RLIMIT_NPROC, self.processes),
+            (libc::RLIMIT_NOFILE, self.open_files),
+            (libc::RLIMIT_FSIZE, self.file_size),
+            (libc::RLIMIT_CORE, self.core_size),
+        ];
+
+        // SAFETY: only `setrlimit` is called, which is async-signal-safe.
+        unsafe {
+            command.pre_exec(move || {
+                for (resource, limit) in limits {
+                    let Some(limit) = limit else {
+                        continue;
+                    };
+
+                    // Only root may raise a hard limit, so never ask for more than the current one
+                    let mut current = libc::rlimit {
+                        rlim_cur: 0,
+                        rlim_max: 0,
+                    };
+                    if libc::getrlimit(resource, &mut current) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+
+                    // `SIGXCPU` at the soft limit, a second before `SIGKILL` at the hard one
+                    let hard = match resource {
+                        libc::RLIMIT_CPU => limit.saturating_add(1),
+                        _ => limit,
+                    }
+                    .min(current.rlim_max);
+                    let rlimit = libc::rlimit {
+                        rlim_cur: limit.min(hard),
+                        rlim_max: hard,
+                    };
+
+                    if libc::setrlimit(resource, &rlimit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+
+                Ok(())
+            });
+        }
+    }
+}
+
+/// How a job's artifacts are returned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Delivery {
+    /// Base64 within the `EndOfOutput` frame
+    #[default]
+    Inline,
+    /// A binary frame each, ahead of the `EndOfOutput` frame
+    Frames,
+}
+
+/// Files a job wants back once it exits, by glob relative to its directory
+#[derive(Clone, Debug, Default)]
+pub struct Artifacts {
+    pub patterns: Vec<String>,
+    pub delivery: Delivery,
+}
+
+impl Artifacts {
+    /// Compiles the patterns, `*` does not cross directories but `**` does
+    pub fn matcher(&self) -> Result<GlobSet, RuntimeError> {
+        let mut set = GlobSetBuilder::new();
+
+        for pattern in &self.patterns {
+            match GlobBuilder::new(pattern).literal_separator(true).build() {
+                Ok(glob) => set.add(glob),
+                Err(err) => return Err(RuntimeError::ParseInput(err.to_string())),
+            };
+        }
+
+        set.build()
+            .map_err(|err| RuntimeError::ParseInput(err.to_string()))
+    }
+}
+
+/// Third-party packages requested by a job, by name and version. They are
+/// only ever resolved from the server's mirror, never from the network.
+#[derive(Clone, Debug, Default)]
+pub struct Dependencies {
+    pub packages: BTreeMap<String, String>,
+    /// Holds a `cargo`, `pip`, `npm` and `go` directory, one per package manager
+    pub mirror: Option<String>,
+}
+
+impl Dependencies {
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Directory of the mirror serving `manager`'s packages
+    pub fn mirror(&self, manager: &str) -> Result<String, RuntimeError> {
+        match &self.mirror {
+            Some(mirror) => Ok(format!("{}/{}", mirror, manager)),
+            None => Err(RuntimeError::DependencyFailure(
+                "No dependency mirror is configured".to_string(),
+            )),
+        }
+    }
+
+    /// Rejects names and versions which could escape the manifests they are written into
+    pub fn validate(&self) -> Result<(), RuntimeError> {
+        for (name, version) in &self.packages {
+            let valid_name = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-/@".contains(c));
+            let valid_version = !version.is_empty()
+                && version
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || ".-+*^~<>=!, ".contains(c));
+
+            if !valid_name || !valid_version {
+                return Err(RuntimeError::DependencyFailure(format!(
+                    "Invalid dependency '{}' at '{}'",
+                    name, version
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Variables read by the dynamic loader, the C library or a shell before the
+/// program's own code runs, which a request may not set. So is any `LD_*`.
+const LOADER_VARIABLES: [&str; 20] = [
+    "PATH",
+    "GCONV_PATH",
+    "GETCONF_DIR",
+    "GLIBC_TUNABLES",
+    "HOSTALIASES",
+    "LOCALDOMAIN",
+    "LOCPATH",
+    "MALLOC_TRACE",
+    "NIS_PATH",
+    "NLSPATH",
+    "RESOLV_HOST_CONF",
+    "RES_OPTIONS",
+    "TZDIR",
+    "BASH_ENV",
+    "ENV",
+    "BASHOPTS",
+    "SHELLOPTS",
+    "PS4",
+    "IFS",
+    "CDPATH",
+];
+
+/// Variables a job's processes start with, in place of the server's own
+#[derive(Clone, Debug, Default)]
+pub struct Environment {
+    /// Set for every process of the job, build steps included
+    pub base: BTreeMap<String, String>,
+    /// Requested for the job's program alone, so never alters its build
+    pub program: BTreeMap<String, String>,
+}
+
+impl Environment {
+    /// Starts `command` with the base environment alone, as build steps and
+    /// whatever is wrapped around the job's program are
+    pub fn base(&self, command: &mut Command) {
+        replace(command, self.base.iter());
+    }
+
+    /// Starts the job's program, `command`, through `env(1)`, which sets the
+    /// requested variables for it alone. Any supervisor or sandbox wrapped
+    /// around it is left with the base environment.
+    pub fn program(&self, command: Command) -> Command {
+        // Those the runner sets itself take precedence
+        let requested: Vec<String> = self
+            .program
+            .iter()
+            .filter(|(key, _)| command.get_envs().all(|(own, _)| own != key.as_str()))
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+
+        if requested.is_empty() {
+            return command;
+        }
+
+        let mut wrapped = Command::new("env");
+        wrapped
+            .arg("--")
+            .args(requested)
+            .arg(command.get_program())
+            .args(command.get_args());
+
+        if let Some(directory) = command.get_current_dir() {
+            wrapped.current_dir(directory);
+        }
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => wrapped.env(key, value),
+                None => wrapped.env_remove(key),
+            };
+        }
+
+        wrapped
+    }
+
+    /// Whether a request may set `key` to `value`. Names may not hold `=`,
+    /// and neither names nor values a NUL. `PATH` is the server's, as the
+    /// job's commands are found through it.
+    pub fn permits(key: &str, value: &str) -> bool {
+        !key.is_empty()
+            && !key.contains(['=', '\0'])
+            && !value.contains('\0')
+            && !key.starts_with("LD_")
+            && !LOADER_VARIABLES.contains(&key)
+    }
+}
+
+/// Clears what `command` would inherit for `variables`. Those it sets itself,
+/// such as a runner's `PYTHONPATH`, take precedence.
+fn replace<'a>(command: &mut Command, variables: impl Iterator<Item = (&'a String, &'a String)>) {
+    let own: Vec<(OsString, Option<OsString>)> = command
+        .get_envs()
+        .map(|(key, value)| (key.to_owned(), value.map(OsStr::to_owned)))
+        .collect();
+
+    command.env_clear().envs(variables);
+    for (key, value) in own {
+        match value {
+            Some(value) => command.env(key, value),
+            None => command.env_remove(key),
+        };
+    }
+}
+
+/// What a job was submitted for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Build, then execute the program
+    #[default]
+    Run,
+    /// Only run the language's checks, reporting their diagnostics
+    Check,
+}
+
+/// Size of a job's pseudo-terminal, in character cells
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub columns: u16,
+}
+
+impl Default for WindowSize {
+    fn default() -> Self {
+        WindowSize {
+            rows: 24,
+            columns: 80,
+        }
+    }
+}
+
+/// Instructions delivered to a job after it has been submitted
+#[derive(Clone, Debug)]
+pub enum ExecutorControl {
+    /// Write to the child's `stdin`
+    StandardInput(String),
+    /// Close the child's `stdin`, signalling EOF
+    CloseInput,
+    /// Kill the job's process group
+    Cancel,
+    /// Resize the program's pseudo-terminal, where it has one
+    Resize(WindowSize),
+}
+
 pub struct ExecutorBuilder {
     language: Option<Languages>,    // Language
     standard_input: Option<String>, // STDIN
-    arguments: Option<String>,      // Command-line Arguments
+    arguments: Option<Arguments>,   // Command-line Arguments
     src_file: Option<String>,       // Sourcefile
+    files: BTreeMap<String, String>,
+    archive: Option<Vec<u8>>,
+    entrypoint: Option<String>,
     nonce: Option<String>,
+    mode: Mode,
+    dependencies: Dependencies,
+    artifacts: Artifacts,
+    sandbox: Sandbox,
+    interactive: bool,
+    framing: Framing,
+    tty: Option<WindowSize>,
+    environment: Option<Environment>,
+    limits: Option<Limits>,
 }
 
 pub struct Executor {
@@ -96,18 +589,50 @@ pub struct Executor {
     pub nonce: Option<String>,
 
     pub language: Languages,
-    pub src_file: String,
+    pub mode: Mode,
+    /// Sources by path, relative to the job's directory
+    pub files: BTreeMap<String, Vec<u8>>,
+    /// A tar, tar.gz or zip of further files, unpacked before the job is built
+    pub archive: Option<Vec<u8>>,
+    /// File the program starts from, amongst `files`
+    pub entrypoint: String,
+    pub dependencies: Dependencies,
+    pub artifacts: Artifacts,
+    pub sandbox: Sandbox,
     pub allocated_dir: String,
+    /// Warm workspace the job is being built in, if its language has a template
+    pub workspace: Option<String>,
 
     pub terminal_feed: TerminalFeed,
     pub commandline_arguments: Arguments,
+    pub limits: Limits,
 
     pub timings: Timing,
     pub broadcast: (Sender<TerminalStream>, Receiver<TerminalStream>),
 
+    /// Keeps `stdin` open after the initial input for [`ExecutorControl`] messages
+    pub interactive: bool,
+    /// How the program's output is divided into events
+    pub framing: Framing,
+    /// Attaches the program to a pseudo-terminal of this size in place of
+    /// its three pipes, merging `stdout` and `stderr`
+    pub tty: Option<WindowSize>,
+    pub environment: Environment,
+    pub control: (
+        UnboundedSender<ExecutorControl>,
+        Option<UnboundedReceiver<ExecutorControl>>,
+    ),
+
     pub sender_id: Uuid,
 }
 
+impl Executor {
+    /// Directory the job's sources are laid out and built in
+    pub fn workspace(&self) -> &str {
+        self.workspace.as_deref().unwrap_or(&self.allocated_dir)
+    }
+}
+
 impl ExecutorBuilder {
     pub fn new() -> Self {
         ExecutorBuilder {
@@ -115,7 +640,19 @@ impl ExecutorBuilder {
             standard_input: None,
             arguments: None,
             src_file: None,
+            files: BTreeMap::new(),
+            archive: None,
+            entrypoint: None,
             nonce: None,
+            mode: Mode::Run,
+            dependencies: Dependencies::default(),
+            artifacts: Artifacts::default(),
+            sandbox: Sandbox::default(),
+            interactive: false,
+            framing: Framing::default(),
+            tty: None,
+            environment: None,
+            limits: None,
         }
     }
 
@@ -124,12 +661,27 @@ impl ExecutorBuilder {
         self
     }
 
-    pub fn src_file(mut self, input: String) -> Self {
-        self.src_file = Some(input);
+    pub fn src_file(mut self, input: Option<String>) -> Self {
+        self.src_file = input;
+        self
+    }
+
+    pub fn files(mut self, files: BTreeMap<String, String>) -> Self {
+        self.files = files;
         self
     }
 
-    pub fn arguments(mut self, arguments: String) -> Self {
+    pub fn archive(mut self, archive: Option<Vec<u8>>) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    pub fn entrypoint(mut self, entrypoint: Option<String>) -> Self {
+        self.entrypoint = entrypoint;
+        self
+    }
+
+    pub fn arguments(mut self, arguments: Arguments) -> Self {
         self.arguments = Some(arguments);
         self
     }
@@ -144,38 +696,238 @@ impl ExecutorBuilder {
         self
     }
 
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn dependencies(mut self, dependencies: Dependencies) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    pub fn artifacts(mut self, artifacts: Artifacts) -> Self {
+        self.artifacts = artifacts;
+        self
+    }
+
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    pub fn tty(mut self, tty: Option<WindowSize>) -> Self {
+        self.tty = tty;
+        self
+    }
+
+    pub fn environment(mut self, environment: Environment) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
     pub fn build(self, sender_id: Uuid) -> Executor {
         let throughput = broadcast::channel::<TerminalStream>(100);
+        let (control_sender, control_receiver) = mpsc::unbounded_channel::<ExecutorControl>();
         let id = Uuid::new_v4();
 
+        let language = self
+            .language
+            .expect("[BUILDER]: Could not retrieve language, value not set.");
+
+        // A lone source is the entrypoint, at the language's default path unless named
+        let entrypoint = self.entrypoint.unwrap_or_else(|| {
+            language
+                .executor()
+                .map_or("main", |executor| executor.entrypoint)
+                .to_string()
+        });
+
+        let mut files: BTreeMap<String, Vec<u8>> = self
+            .files
+            .into_iter()
+            .map(|(path, contents)| (path, contents.into_bytes()))
+            .collect();
+        if let Some(source) = self.src_file {
+            files.insert(entrypoint.clone(), source.into_bytes());
+        }
+
         Executor {
             id,
             nonce: self.nonce.clone(),
             broadcast: throughput,
-            language: self
-                .language
-                .expect("[BUILDER]: Could not retrieve language, value not set."),
-            src_file: self
-                .src_file
-                .expect("[BUILDER]: Could not retrieve source file, value not set."),
+            mode: self.mode,
+            dependencies: self.dependencies,
+            artifacts: self.artifacts,
+            sandbox: self.sandbox,
+            interactive: self.interactive,
+            framing: self.framing,
+            tty: self.tty,
+            environment: self
+                .environment
+                .expect("[BUILDER]: Could not retrieve environment, value not set."),
+            control: (control_sender, Some(control_receiver)),
+            limits: self
+                .limits
+                .expect("[BUILDER]: Could not retrieve limits, value not set."),
+            language,
+            files,
+            archive: self.archive,
+            entrypoint,
             terminal_feed: TerminalFeed {
                 std_cout: vec![],
                 std_cin: vec![TerminalStream::new(
                     TerminalStreamType::StandardInput,
-                    self.standard_input.unwrap_or(String::new()),
+                    self.standard_input.unwrap_or_default(),
                     self.nonce,
                 )],
                 std_err: vec![],
                 output: vec![],
             },
             timings: Timing {
-                time_received: None,
+                time_received: Some(Utc::now()),
                 time_executed: None,
                 time_completed: None,
             },
             sender_id,
             allocated_dir: format!("jobs/{}/{}", sender_id, id),
-            commandline_arguments: Arguments::parse(self.arguments.unwrap_or(String::new())),
+            workspace: None,
+            commandline_arguments: self.arguments.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arguments, CommandlineArguments, Dependencies, Environment};
+    use crate::lang::RuntimeError;
+
+    fn parse(arguments: CommandlineArguments) -> Vec<String> {
+        Arguments::parse(arguments).unwrap().arguments
+    }
+
+    #[test]
+    fn splits_a_line_as_a_shell_would() {
+        let line = CommandlineArguments::Line(r#"a "b c" 'd e' f\ g"#.to_string());
+        assert_eq!(parse(line), ["a", "b c", "d e", "f g"]);
+
+        let empty = CommandlineArguments::Line("  ".to_string());
+        assert!(parse(empty).is_empty());
+    }
+
+    #[test]
+    fn takes_a_list_as_it_is() {
+        let list = vec!["a b".to_string(), "".to_string(), "'c'".to_string()];
+        assert_eq!(parse(CommandlineArguments::List(list.clone())), list);
+    }
+
+    #[test]
+    fn counts_arguments() {
+        let line = CommandlineArguments::Line("a b c".to_string());
+        assert_eq!(Arguments::parse(line).unwrap().argument_count, 3);
+    }
+
+    #[test]
+    fn refuses_an_unclosed_quote() {
+        let line = CommandlineArguments::Line("a \"b".to_string());
+        assert!(Arguments::parse(line).is_err());
+    }
+
+    #[test]
+    fn permits_ordinary_variables() {
+        assert!(Environment::permits("RUST_BACKTRACE", "1"));
+        assert!(Environment::permits("GREETING", "a=b c"));
+        assert!(Environment::permits("LANG", ""));
+    }
+
+    #[test]
+    fn refuses_malformed_variables() {
+        assert!(!Environment::permits("", "value"));
+        assert!(!Environment::permits("A=B", "value"));
+        assert!(!Environment::permits("A\0", "value"));
+        assert!(!Environment::permits("A", "val\0ue"));
+    }
+
+    #[test]
+    fn refuses_loader_variables() {
+        for key in [
+            "PATH",
+            "LD_PRELOAD",
+            "LD_LIBRARY_PATH",
+            "GCONV_PATH",
+            "BASH_ENV",
+        ] {
+            assert!(!Environment::permits(key, "/tmp/x"), "{}", key);
         }
     }
+
+    fn dependencies(packages: &[(&str, &str)]) -> Dependencies {
+        Dependencies {
+            packages: packages
+                .iter()
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect(),
+            mirror: Some("/srv/mirror".to_string()),
+        }
+    }
+
+    #[test]
+    fn accepts_package_names_and_versions() {
+        let packages = dependencies(&[
+            ("rand", "0.8.5"),
+            ("serde_json", "^1.0"),
+            ("@types/node", "20.1.0"),
+            ("github.com/google/uuid", "v1.6.0"),
+            ("requests", ">=2.31, <3"),
+        ]);
+
+        assert!(packages.validate().is_ok());
+    }
+
+    #[test]
+    fn refuses_what_could_escape_a_manifest() {
+        for (name, version) in [
+            ("", "1.0"),
+            ("rand", ""),
+            ("rand = { path = \"/\" }\nx", "1.0"),
+            ("rand", "1.0\"\n[patch.crates-io]"),
+            ("left-pad", "file:../../etc"),
+            ("requests", "2.31; python_version"),
+        ] {
+            match dependencies(&[(name, version)]).validate() {
+                Err(RuntimeError::DependencyFailure(_)) => {}
+                other => panic!("{:?} at {:?} gave {:?}", name, version, other),
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_only_from_the_mirror() {
+        let packages = dependencies(&[("rand", "0.8.5")]);
+        assert_eq!(packages.mirror("cargo").unwrap(), "/srv/mirror/cargo");
+
+        let unmirrored = Dependencies {
+            mirror: None,
+            ..packages
+        };
+        assert!(matches!(
+            unmirrored.mirror("cargo"),
+            Err(RuntimeError::DependencyFailure(_))
+        ));
+    }
 }
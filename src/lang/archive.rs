@@ -0,0 +1,288 @@
+use crate::exec::{Executor, Limits};
+use crate::lang::RuntimeError;
+use flate2::read::GzDecoder;
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::EntryType;
+use zip::ZipArchive;
+
+const GZIP: &[u8] = &[0x1f, 0x8b];
+const ZIP: &[u8] = b"PK\x03\x04";
+const EMPTY_ZIP: &[u8] = b"PK\x05\x06";
+/// Offset of the `ustar` magic within a tar header
+const USTAR: usize = 257;
+
+/// Files read out of an archive so far, counted against the job's limits
+struct Unpacked<'a> {
+    files: BTreeMap<String, Vec<u8>>,
+    size: u64,
+    limits: &'a Limits,
+}
+
+impl Unpacked<'_> {
+    fn insert(&mut self, path: &Path, reader: impl Read) -> Result<(), RuntimeError> {
+        if self.files.len() >= self.limits.archive_files {
+            return Err(invalid(format!(
+                "Archive holds more than {} files",
+                self.limits.archive_files
+            )));
+        }
+
+        let path = normalise(path)?;
+
+        // Read one byte past the limit, so a lying header cannot sneak more in
+        let remaining = self.limits.archive_size - self.size;
+        let mut contents = vec![];
+        reader
+            .take(remaining + 1)
+            .read_to_end(&mut contents)
+            .map_err(invalid)?;
+
+        if contents.len() as u64 > remaining {
+            return Err(invalid(format!(
+                "Archive unpacks to more than {} bytes",
+                self.limits.archive_size
+            )));
+        }
+
+        self.size += contents.len() as u64;
+        self.files.insert(path, contents);
+
+        Ok(())
+    }
+}
+
+/// Unpacks the job's archive into its files. Files given alongside the
+/// archive take precedence over those within it.
+pub fn unpack_archive(exec: &mut Executor) -> Result<(), RuntimeError> {
+    let Some(archive) = exec.archive.take() else {
+        return Ok(());
+    };
+
+    let mut unpacked = Unpacked {
+        files: BTreeMap::new(),
+        size: 0,
+        limits: &exec.limits,
+    };
+
+    if archive.starts_with(GZIP) {
+        unpack_tar(GzDecoder::new(archive.as_slice()), &mut unpacked)?;
+    } else if archive.starts_with(ZIP) || archive.starts_with(EMPTY_ZIP) {
+        unpack_zip(&archive, &mut unpacked)?;
+    } else if archive.get(USTAR..USTAR + 5) == Some(b"ustar") {
+        unpack_tar(archive.as_slice(), &mut unpacked)?;
+    } else {
+        return Err(invalid("Expected a tar, tar.gz or zip archive"));
+    }
+
+    for (path, contents) in unpacked.files {
+        exec.files.entry(path).or_insert(contents);
+    }
+
+    Ok(())
+}
+
+fn unpack_tar(reader: impl Read, unpacked: &mut Unpacked) -> Result<(), RuntimeError> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries().map_err(invalid)? {
+        let entry = entry.map_err(invalid)?;
+        let path = entry.path().map_err(invalid)?.into_owned();
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => unpacked.insert(&path, entry)?,
+            // Written by `git archive`, holds the commit rather than a file
+            EntryType::Directory | EntryType::XGlobalHeader => {}
+            EntryType::Symlink | EntryType::Link => return Err(link(&path)),
+            _ => {
+                return Err(invalid(format!(
+                    "'{}' is not a regular file",
+                    path.display()
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn unpack_zip(archive: &[u8], unpacked: &mut Unpacked) -> Result<(), RuntimeError> {
+    let mut archive = ZipArchive::new(Cursor::new(archive)).map_err(invalid)?;
+
+    for index in 0..archive.len() {
+        let file = archive.by_index(index).map_err(invalid)?;
+        let path = PathBuf::from(file.name());
+
+        if file.is_dir() {
+            continue;
+        }
+
+        if file
+            .unix_mode()
+            .is_some_and(|mode| mode & libc::S_IFMT == libc::S_IFLNK)
+        {
+            return Err(link(&path));
+        }
+
+        unpacked.insert(&path, file)?;
+    }
+
+    Ok(())
+}
+
+/// Joins the path's components with `/`, refusing any which leave the job
+fn normalise(path: &Path) -> Result<String, RuntimeError> {
+    let mut parts = vec![];
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::CurDir => {}
+            _ => {
+                return Err(invalid(format!(
+                    "'{}' is not a relative path within the job",
+                    path.display()
+                )))
+            }
+        }
+    }
+
+    match parts.is_empty() {
+        true => Err(invalid(format!("'{}' is not a file", path.display()))),
+        false => Ok(parts.join("/")),
+    }
+}
+
+fn link(path: &Path) -> RuntimeError {
+    invalid(format!(
+        "'{}' is a link, which are not allowed",
+        path.display()
+    ))
+}
+
+fn invalid(err: impl ToString) -> RuntimeError {
+    RuntimeError::InvalidArchive(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalise, unpack_tar, unpack_zip, Unpacked};
+    use crate::exec::Limits;
+    use crate::lang::RuntimeError;
+    use std::collections::BTreeMap;
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+    use std::time::Duration;
+    use tar::{EntryType, Header};
+    use zip::ZipWriter;
+
+    fn limits(archive_files: usize, archive_size: u64) -> Limits {
+        Limits {
+            time_limit: Duration::ZERO,
+            compile_time_limit: Duration::ZERO,
+            idle_timeout: None,
+            kill_grace_period: Duration::ZERO,
+            archive_size,
+            archive_files,
+            artifact_size: 0,
+            artifact_files: 0,
+            run_resources: Default::default(),
+            compile_resources: Default::default(),
+            output: Default::default(),
+        }
+    }
+
+    fn tar(entries: &[(&str, EntryType, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, entry_type, contents) in entries {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(*entry_type);
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, *contents).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (path, contents) in entries {
+            writer.start_file(*path, Default::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn unpack(
+        archive: &[u8],
+        limits: &Limits,
+        unpack: fn(&[u8], &mut Unpacked) -> Result<(), RuntimeError>,
+    ) -> Result<BTreeMap<String, Vec<u8>>, RuntimeError> {
+        let mut unpacked = Unpacked {
+            files: BTreeMap::new(),
+            size: 0,
+            limits,
+        };
+        unpack(archive, &mut unpacked).map(|_| unpacked.files)
+    }
+
+    fn from_tar(archive: &[u8], unpacked: &mut Unpacked) -> Result<(), RuntimeError> {
+        unpack_tar(archive, unpacked)
+    }
+
+    #[test]
+    fn unpacks_tar_and_zip() {
+        let limits = limits(8, 64);
+
+        let files = unpack(
+            &tar(&[("./src/a.py", EntryType::Regular, b"a")]),
+            &limits,
+            from_tar,
+        );
+        assert_eq!(files.unwrap()["src/a.py"], b"a");
+
+        let files = unpack(&zip(&[("src/b.py", b"b")]), &limits, unpack_zip);
+        assert_eq!(files.unwrap()["src/b.py"], b"b");
+    }
+
+    #[test]
+    fn refuses_too_many_files() {
+        let limits = limits(1, 64);
+        let entries = [("a", b"a" as &[u8]), ("b", b"b")];
+
+        assert!(unpack(&zip(&entries), &limits, unpack_zip).is_err());
+        assert!(unpack(&zip(&entries[..1]), &limits, unpack_zip).is_ok());
+    }
+
+    #[test]
+    fn refuses_too_many_bytes() {
+        let limits = limits(8, 4);
+
+        let over = tar(&[
+            ("a", EntryType::Regular, b"abc"),
+            ("b", EntryType::Regular, b"de"),
+        ]);
+        assert!(unpack(&over, &limits, from_tar).is_err());
+
+        let within = tar(&[
+            ("a", EntryType::Regular, b"abc"),
+            ("b", EntryType::Regular, b"d"),
+        ]);
+        assert!(unpack(&within, &limits, from_tar).is_ok());
+    }
+
+    #[test]
+    fn refuses_links() {
+        let archive = tar(&[("a", EntryType::Symlink, b"")]);
+        assert!(unpack(&archive, &limits(8, 64), from_tar).is_err());
+    }
+
+    #[test]
+    fn refuses_paths_leaving_the_job() {
+        for path in ["../a", "/etc/passwd", "a/../../b", "."] {
+            assert!(normalise(Path::new(path)).is_err(), "{}", path);
+        }
+        assert_eq!(normalise(Path::new("./a/./b")).unwrap(), "a/b");
+    }
+}
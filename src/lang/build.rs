@@ -0,0 +1,224 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command as LinearCommand, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::exec::{Executor, ExecutorControl, TerminalStream, TerminalStreamType};
+use crate::lang::TerminationReason;
+use crate::lang::{signal_group, spawn, Diagnostic, DiagnosticFormat, RuntimeError};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::broadcast::Sender;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::{sleep_until, timeout};
+
+/// How long a build step's output may keep draining once the step has ended
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A command run ahead of the program itself, such as a compiler invocation
+pub struct BuildStep {
+    pub command: LinearCommand,
+    pub format: DiagnosticFormat,
+}
+
+impl BuildStep {
+    pub fn new(command: LinearCommand, format: DiagnosticFormat) -> Self {
+        BuildStep { command, format }
+    }
+}
+
+/// Outcome of a job's build steps
+pub struct Build {
+    pub exit_status: Option<ExitStatus>,
+    pub reason: TerminationReason,
+    pub duration: Duration,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Build {
+    pub fn succeeded(&self) -> bool {
+        self.reason == TerminationReason::Exited
+            && !matches!(self.exit_status, Some(status) if !status.success())
+    }
+}
+
+/// Copies `artifacts`, relative paths, from one directory into another
+pub fn copy_artifacts(from: &Path, to: &Path, artifacts: &[&str]) -> io::Result<()> {
+    for artifact in artifacts {
+        let destination = to.join(artifact);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::copy(from.join(artifact), destination)?;
+    }
+
+    Ok(())
+}
+
+/// Runs each step in turn, streaming its output as `CompilerOutput` and
+/// stopping at the first which fails. Control messages other than `Cancel`
+/// are held in `pending` until the program itself has started.
+pub async fn build(
+    exec: &Executor,
+    steps: Vec<BuildStep>,
+    control: &mut UnboundedReceiver<ExecutorControl>,
+    pending: &mut Vec<ExecutorControl>,
+) -> Result<Build, RuntimeError> {
+    let start_time = Instant::now();
+    let deadline = start_time + exec.limits.compile_time_limit;
+
+    let mut build = Build {
+        exit_status: None,
+        reason: TerminationReason::Exited,
+        duration: Duration::ZERO,
+        diagnostics: vec![],
+    };
+
+    for BuildStep { command, format } in steps {
+        let program = command.get_program().to_string_lossy().to_string();
+
+        let mut command = exec
+            .sandbox
+            .wrap(command, &[exec.workspace(), &exec.allocated_dir])?;
+        exec.environment.base(&mut command);
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = match spawn(command, exec.limits.compile_resources, None) {
+            Ok(child) => child,
+            Err(err) => {
+                return Err(RuntimeError::InitializationFailure(format!(
+                    "Command: '{}' in '{}': {}",
+                    program,
+                    exec.workspace(),
+                    err
+                )))
+            }
+        };
+
+        let pid = child.id();
+        let stdout = tokio::spawn(stream(
+            child
+                .stdout
+                .take()
+                .expect("Internal error, could not take stdout"),
+            format,
+            true,
+            exec.broadcast.0.clone(),
+            exec.nonce.clone(),
+        ));
+        let stderr = tokio::spawn(stream(
+            child
+                .stderr
+                .take()
+                .expect("Internal error, could not take stderr"),
+            format,
+            false,
+            exec.broadcast.0.clone(),
+            exec.nonce.clone(),
+        ));
+
+        let exit_status = loop {
+            tokio::select! {
+                status = child.wait() => match status {
+                    Ok(exit_status) => break exec.sandbox.status(exit_status),
+                    Err(err) => return Err(RuntimeError::Capture(err.to_string())),
+                },
+                Some(instruction) = control.recv() => match instruction {
+                    ExecutorControl::Cancel => {
+                        signal_group(pid, libc::SIGKILL);
+                        build.reason = TerminationReason::Cancelled;
+                    }
+                    instruction => pending.push(instruction),
+                },
+                _ = sleep_until(deadline.into()), if build.reason == TerminationReason::Exited => {
+                    signal_group(pid, libc::SIGKILL);
+                    build.reason = TerminationReason::CompileTimeLimitExceeded;
+                }
+            }
+        };
+
+        // A process which left the group may still hold a pipe open
+        for mut reader in [stdout, stderr] {
+            match timeout(DRAIN_TIMEOUT, &mut reader).await {
+                Ok(diagnostics) => build.diagnostics.extend(diagnostics.unwrap_or_default()),
+                Err(_) => reader.abort(),
+            }
+        }
+
+        if build.reason == TerminationReason::Exited {
+            build.reason = TerminationReason::from_limit(&exit_status).unwrap_or(build.reason);
+        }
+
+        build.exit_status = Some(exit_status);
+        if !build.succeeded() {
+            break;
+        }
+    }
+
+    build.duration = start_time.elapsed();
+    Ok(build)
+}
+
+/// Forwards a build step's output line by line, collecting any diagnostics
+async fn stream<R: AsyncRead + Unpin>(
+    pipe: R,
+    format: DiagnosticFormat,
+    is_stdout: bool,
+    sender: Sender<TerminalStream>,
+    nonce: Option<String>,
+) -> Vec<Diagnostic> {
+    let mut reader = BufReader::new(pipe);
+    let mut buffer = vec![];
+    let mut diagnostics = vec![];
+    let mut context = None;
+
+    loop {
+        buffer.clear();
+        match reader.read_until(b'\n', &mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        // Compilers may echo bytes of the source which aren't valid UTF-8
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line
+            .trim_end_matches('\n')
+            .trim_end_matches('\r')
+            .to_string();
+
+        let text = match format {
+            DiagnosticFormat::Cargo if is_stdout => match Diagnostic::from_cargo(&line) {
+                Some((diagnostic, rendered)) => {
+                    diagnostics.extend(diagnostic);
+                    rendered.trim_end().to_string()
+                }
+                // Artifact and build-script notices, not meant for the client
+                None => continue,
+            },
+            DiagnosticFormat::Positional => {
+                diagnostics.extend(Diagnostic::from_positional(&line));
+                line
+            }
+            DiagnosticFormat::Bun => {
+                diagnostics.extend(Diagnostic::from_bun(&line, &mut context));
+                line
+            }
+            _ => line,
+        };
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let _ = sender.send(TerminalStream::new(
+            TerminalStreamType::CompilerOutput,
+            text,
+            nonce.clone(),
+        ));
+    }
+
+    diagnostics
+}
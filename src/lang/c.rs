@@ -1,67 +1,63 @@
 use crate::exec::Executor;
-use crate::lang::RuntimeError;
-use std::{
-    process::{Command as LinearCommand, Stdio},
-    time::Instant,
+use crate::lang::{
+    sources_with, write_sources, BuildStep, DiagnosticFormat, LanguageExecutor, RuntimeError,
+};
+use std::process::Command as LinearCommand;
+
+pub const EXECUTOR: LanguageExecutor = LanguageExecutor {
+    prepare,
+    compile,
+    check,
+    execute,
+    toolchain: &["gcc", "--version"],
+    artifacts: &["exec.out"],
+    template: None,
+    reserved: &[],
+    entrypoint: "main.c",
 };
-use tokio::{process::Command, sync::MutexGuard};
 
-use super::ChildWrapper;
+fn prepare(exec: &Executor) -> Result<(), RuntimeError> {
+    if !exec.dependencies.is_empty() {
+        return Err(RuntimeError::DependencyFailure(
+            "C does not support dependencies".to_string(),
+        ));
+    }
 
-pub fn run(exec: &MutexGuard<Executor>) -> Result<ChildWrapper, RuntimeError> {
-    // Create File and Fill
-    let file_dir = exec.allocated_dir.to_string();
-    let file_contents: String = exec.src_file.clone();
+    write_sources(exec)
+}
 
-    match std::fs::write(format!("{}/main.c", file_dir), file_contents) {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::WriteFailed(err.to_string())),
-    }
+/// Every translation unit in the tree, headers are found relative to its root
+fn units(exec: &Executor) -> Vec<String> {
+    sources_with(exec, &["c"])
+}
 
+fn compile(exec: &Executor) -> Vec<BuildStep> {
     // Compile File
-    let compiler = match LinearCommand::new("gcc")
-        .current_dir(&file_dir)
-        .args(["-o", "exec.out", "main.c"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(val) => val,
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: 'gcc {} {} ' in '{}': {}",
-                "-o exec.out", "main.c", file_dir, err
-            )))
-        }
-    };
+    let mut compiler = LinearCommand::new("gcc");
+    compiler
+        .current_dir(exec.workspace())
+        .args(["-fdiagnostics-color=never", "-I.", "-o", "exec.out"])
+        .args(units(exec));
+
+    vec![BuildStep::new(compiler, DiagnosticFormat::Positional)]
+}
 
-    let err_output = String::from_utf8(compiler.wait_with_output().unwrap().stderr).unwrap();
-    println!("Running gcc -o exec main.c: {:?}", err_output);
+fn check(exec: &Executor) -> Vec<BuildStep> {
+    let mut compiler = LinearCommand::new("gcc");
+    compiler
+        .current_dir(exec.workspace())
+        .args(["-fdiagnostics-color=never", "-I.", "-fsyntax-only"])
+        .args(units(exec));
 
-    let new_args = exec.commandline_arguments.arguments.clone();
-    let now = Instant::now();
+    vec![BuildStep::new(compiler, DiagnosticFormat::Positional)]
+}
 
+fn execute(exec: &Executor) -> LinearCommand {
     // Execute File
-    let execution = match Command::new("./exec.out")
-        .current_dir(&file_dir)
-        .args(new_args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(val) => val,
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: './exec.out' in '{}': {}",
-                file_dir, err
-            )))
-        }
-    };
+    let mut command = LinearCommand::new("./exec.out");
+    command
+        .current_dir(&exec.allocated_dir)
+        .args(exec.commandline_arguments.arguments.clone());
 
-    Ok(ChildWrapper {
-        child: execution,
-        start_time: now,
-    })
+    command
 }
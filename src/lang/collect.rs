@@ -0,0 +1,337 @@
+use crate::exec::Executor;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use globset::GlobSet;
+use serde::{Serialize, Serializer};
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+/// Bytes of a file enough to tell its type, as `infer` reads them
+const SNIFFED: u64 = 8192;
+
+/// A file left behind by a job which matched one of its `artifacts` globs
+#[derive(Clone, Debug, Serialize)]
+pub struct Artifact {
+    /// Relative to the job's directory
+    pub path: String,
+    pub mime: String,
+    pub size: u64,
+    /// Base64 within the `EndOfOutput` frame, absent once sent in a frame of its own
+    #[serde(serialize_with = "as_base64", skip_serializing_if = "Option::is_none")]
+    pub contents: Option<Vec<u8>>,
+    /// Matched, but beyond the job's artifact limits, so never read
+    pub omitted: bool,
+}
+
+#[derive(Serialize)]
+struct ArtifactHeader<'a> {
+    nonce: Option<&'a str>,
+    #[serde(flatten)]
+    artifact: &'a Artifact,
+}
+
+/// Collects the files in the job's directory which match `matcher`, in path
+/// order, until its limits on their count and total size are reached.
+pub fn collect_artifacts(exec: &Executor, matcher: &GlobSet) -> Vec<Artifact> {
+    if matcher.is_empty() {
+        return vec![];
+    }
+
+    let root = Path::new(&exec.allocated_dir);
+    let mut found = vec![];
+    walk(root, root, &mut found);
+    found.sort();
+
+    let mut total = 0;
+    let mut artifacts: Vec<Artifact> = vec![];
+
+    for path in found {
+        if !matcher.is_match(&path) {
+            continue;
+        }
+
+        // The file may have been replaced since the walk, so it is checked once open
+        let Some((file, size)) = open_regular(&root.join(&path)) else {
+            continue;
+        };
+
+        let collected = artifacts
+            .iter()
+            .filter(|artifact| !artifact.omitted)
+            .count();
+        let fits =
+            collected < exec.limits.artifact_files && total + size <= exec.limits.artifact_size;
+
+        let mut read = vec![];
+        let limit = match fits {
+            true => size,
+            false => SNIFFED,
+        };
+        if file.take(limit).read_to_end(&mut read).is_err() {
+            continue;
+        }
+
+        let sniffed = infer::get(&read).map(|kind| kind.mime_type().to_string());
+        let contents = match fits {
+            true => Some(read),
+            false => None,
+        };
+
+        let mime = sniffed.unwrap_or_else(|| {
+            match contents
+                .as_deref()
+                .is_some_and(|contents| std::str::from_utf8(contents).is_ok())
+            {
+                true => "text/plain".to_string(),
+                false => "application/octet-stream".to_string(),
+            }
+        });
+
+        total += contents
+            .as_ref()
+            .map_or(0, |contents| contents.len() as u64);
+        artifacts.push(Artifact {
+            path,
+            mime,
+            size,
+            omitted: contents.is_none(),
+            contents,
+        });
+    }
+
+    artifacts
+}
+
+/// Opens `location` without following a link, provided it is a regular file,
+/// with its size as of opening. FIFOs and the like never block the open.
+fn open_regular(location: &Path) -> Option<(File, u64)> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(location)
+        .ok()?;
+
+    let metadata = file.metadata().ok()?;
+    match metadata.is_file() {
+        true => Some((file, metadata.len())),
+        false => None,
+    }
+}
+
+/// Frames an artifact the way archives are uploaded: the length of a JSON
+/// header as a big-endian `u32`, the header, then the file's contents.
+pub fn artifact_frame(nonce: Option<&str>, artifact: &Artifact, contents: &[u8]) -> Vec<u8> {
+    let header = serde_json::to_vec(&ArtifactHeader { nonce, artifact }).unwrap();
+
+    let mut frame = Vec::with_capacity(4 + header.len() + contents.len());
+    frame.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(contents);
+
+    frame
+}
+
+/// Regular files beneath `directory`, by path relative to `root`. Links are
+/// never followed, so a job cannot return files from outside its directory.
+fn walk(root: &Path, directory: &Path, found: &mut Vec<String>) {
+    for entry in fs::read_dir(directory).into_iter().flatten().flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        let path = entry.path();
+        if file_type.is_dir() {
+            walk(root, &path, found);
+        } else if file_type.is_file() {
+            if let Ok(relative) = path.strip_prefix(root) {
+                found.push(relative.to_string_lossy().to_string());
+            }
+        }
+    }
+}
+
+fn as_base64<S>(contents: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match contents {
+        Some(contents) => serializer.serialize_str(&STANDARD.encode(contents)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{artifact_frame, collect_artifacts, Artifact};
+    use crate::exec::{Artifacts, Environment, Executor, ExecutorBuilder, Limits, OutputLimits};
+    use crate::lang::Languages;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    /// A job whose directory holds `files`, returning at most `size` bytes of `count` artifacts
+    fn job(files: &[(&str, &[u8])], size: u64, count: usize) -> Executor {
+        let mut exec = ExecutorBuilder::new()
+            .language(Languages::Python)
+            .environment(Environment::default())
+            .limits(Limits {
+                time_limit: Duration::from_secs(1),
+                compile_time_limit: Duration::from_secs(1),
+                idle_timeout: None,
+                kill_grace_period: Duration::from_secs(1),
+                archive_size: 0,
+                archive_files: 0,
+                artifact_size: size,
+                artifact_files: count,
+                run_resources: Default::default(),
+                compile_resources: Default::default(),
+                output: OutputLimits::default(),
+            })
+            .build(Uuid::nil());
+
+        let directory = std::env::temp_dir().join(format!("roadrunner-{}", exec.id));
+        for (path, contents) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        exec.allocated_dir = directory.to_string_lossy().to_string();
+        exec
+    }
+
+    fn collect(exec: &Executor, patterns: &[&str]) -> Vec<Artifact> {
+        let artifacts = Artifacts {
+            patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let collected = collect_artifacts(exec, &artifacts.matcher().unwrap());
+        let _ = fs::remove_dir_all(&exec.allocated_dir);
+        collected
+    }
+
+    #[test]
+    fn collects_matches_in_path_order() {
+        let exec = job(
+            &[
+                ("plot.png", PNG),
+                ("out/b.txt", b"b"),
+                ("out/a.txt", b"a"),
+                ("out/deep/c.txt", b"c"),
+                ("main.py", b""),
+            ],
+            1 << 20,
+            8,
+        );
+
+        let artifacts = collect(&exec, &["*.png", "out/*.txt"]);
+        let paths: Vec<&str> = artifacts.iter().map(|a| a.path.as_str()).collect();
+
+        assert_eq!(paths, ["out/a.txt", "out/b.txt", "plot.png"]);
+        assert_eq!(artifacts[2].contents.as_deref(), Some(PNG));
+    }
+
+    #[test]
+    fn infers_the_mime_type() {
+        let exec = job(
+            &[
+                ("a.png", PNG),
+                ("b.txt", b"total: 3\n"),
+                ("c.bin", b"\xff\xfe\0"),
+            ],
+            1 << 20,
+            8,
+        );
+
+        let mimes: Vec<String> = collect(&exec, &["*"]).into_iter().map(|a| a.mime).collect();
+        assert_eq!(
+            mimes,
+            ["image/png", "text/plain", "application/octet-stream"]
+        );
+    }
+
+    #[test]
+    fn refuses_links_and_special_files() {
+        let exec = job(&[("real.txt", b"real")], 1 << 20, 8);
+        let directory = Path::new(&exec.allocated_dir);
+
+        symlink("/etc/passwd", directory.join("passwd.txt")).unwrap();
+        symlink("real.txt", directory.join("alias.txt")).unwrap();
+        symlink("/etc", directory.join("etc")).unwrap();
+
+        let fifo = std::ffi::CString::new(directory.join("pipe.txt").to_str().unwrap()).unwrap();
+        // SAFETY: `mkfifo` is given a valid, NUL terminated path.
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+        let paths: Vec<String> = collect(&exec, &["**"])
+            .into_iter()
+            .map(|a| a.path)
+            .collect();
+        assert_eq!(paths, ["real.txt"]);
+    }
+
+    #[test]
+    fn omits_what_is_beyond_the_limits() {
+        let exec = job(
+            &[("a.txt", b"1234"), ("b.png", PNG), ("c.txt", b"56")],
+            8,
+            8,
+        );
+
+        let artifacts = collect(&exec, &["*"]);
+        let omitted: Vec<bool> = artifacts.iter().map(|a| a.omitted).collect();
+        assert_eq!(omitted, [false, true, false]);
+
+        // Never read in full, yet still described
+        assert!(artifacts[1].contents.is_none());
+        assert_eq!(
+            (artifacts[1].size, artifacts[1].mime.as_str()),
+            (16, "image/png")
+        );
+
+        let exec = job(&[("a.txt", b"1"), ("b.txt", b"2")], 1 << 20, 1);
+        let omitted: Vec<bool> = collect(&exec, &["*"]).iter().map(|a| a.omitted).collect();
+        assert_eq!(omitted, [false, true]);
+    }
+
+    #[test]
+    fn encodes_contents_as_base64() {
+        let artifact = Artifact {
+            path: "c.bin".to_string(),
+            mime: "application/octet-stream".to_string(),
+            size: 3,
+            contents: Some(b"\xff\xfe\0".to_vec()),
+            omitted: false,
+        };
+
+        let value = serde_json::to_value(&artifact).unwrap();
+        assert_eq!(value["contents"], "//4A");
+    }
+
+    #[test]
+    fn frames_a_header_then_the_raw_contents() {
+        let artifact = Artifact {
+            path: "c.bin".to_string(),
+            mime: "application/octet-stream".to_string(),
+            size: 3,
+            contents: None,
+            omitted: false,
+        };
+
+        let frame = artifact_frame(Some("n"), &artifact, b"\xff\xfe\0");
+        let length = u32::from_be_bytes(frame[..4].try_into().unwrap()) as usize;
+        let header: serde_json::Value = serde_json::from_slice(&frame[4..4 + length]).unwrap();
+
+        assert_eq!(header["nonce"], "n");
+        assert_eq!(header["path"], "c.bin");
+        assert_eq!(header["size"], 3);
+        assert!(header.get("contents").is_none());
+        assert_eq!(&frame[4 + length..], b"\xff\xfe\0");
+    }
+}
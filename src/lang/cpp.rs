@@ -1,66 +1,63 @@
-use crate::lang::RuntimeError;
-use crate::{exec::Executor, lang::ChildWrapper};
-use std::{
-    process::{Command as LinearCommand, Stdio},
-    time::Instant,
+use crate::exec::Executor;
+use crate::lang::{
+    sources_with, write_sources, BuildStep, DiagnosticFormat, LanguageExecutor, RuntimeError,
 };
-use tokio::process::Command;
-use tokio::sync::MutexGuard;
+use std::process::Command as LinearCommand;
 
-pub fn run(exec: &MutexGuard<Executor>) -> Result<ChildWrapper, RuntimeError> {
-    // Create File and Fill
-    let file_dir = exec.allocated_dir.to_string();
-    let file_contents: String = exec.src_file.clone();
+pub const EXECUTOR: LanguageExecutor = LanguageExecutor {
+    prepare,
+    compile,
+    check,
+    execute,
+    toolchain: &["g++", "--version"],
+    artifacts: &["exec.out"],
+    template: None,
+    reserved: &[],
+    entrypoint: "main.cpp",
+};
 
-    match std::fs::write(format!("{}/main.cpp", file_dir), file_contents) {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::WriteFailed(err.to_string())),
+fn prepare(exec: &Executor) -> Result<(), RuntimeError> {
+    if !exec.dependencies.is_empty() {
+        return Err(RuntimeError::DependencyFailure(
+            "C++ does not support dependencies".to_string(),
+        ));
     }
 
+    write_sources(exec)
+}
+
+/// Every translation unit in the tree, headers are found relative to its root
+fn units(exec: &Executor) -> Vec<String> {
+    sources_with(exec, &["cpp", "cc", "cxx"])
+}
+
+fn compile(exec: &Executor) -> Vec<BuildStep> {
     // Compile File
-    let compiler = match LinearCommand::new("g++")
-        .current_dir(&file_dir)
-        .args(["-o", "exec.out", "main.cpp"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(val) => val,
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: 'g++ {} {} ' in '{}': {}",
-                "-o exec.out", "main.cpp", file_dir, err
-            )))
-        }
-    };
+    let mut compiler = LinearCommand::new("g++");
+    compiler
+        .current_dir(exec.workspace())
+        .args(["-fdiagnostics-color=never", "-I.", "-o", "exec.out"])
+        .args(units(exec));
+
+    vec![BuildStep::new(compiler, DiagnosticFormat::Positional)]
+}
 
-    let err_output = String::from_utf8(compiler.wait_with_output().unwrap().stderr).unwrap();
-    println!("Running gcc -o exec main.cpp: {:?}", err_output);
+fn check(exec: &Executor) -> Vec<BuildStep> {
+    let mut compiler = LinearCommand::new("g++");
+    compiler
+        .current_dir(exec.workspace())
+        .args(["-fdiagnostics-color=never", "-I.", "-fsyntax-only"])
+        .args(units(exec));
 
-    let new_args = exec.commandline_arguments.arguments.clone();
-    let now = Instant::now();
+    vec![BuildStep::new(compiler, DiagnosticFormat::Positional)]
+}
 
+fn execute(exec: &Executor) -> LinearCommand {
     // Execute File
-    let execution = match Command::new("./exec.out")
-        .current_dir(&file_dir)
-        .args(new_args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(val) => val,
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: './exec.out' in '{}': {}",
-                file_dir, err
-            )))
-        }
-    };
+    let mut command = LinearCommand::new("./exec.out");
+    command
+        .current_dir(&exec.allocated_dir)
+        .args(exec.commandline_arguments.arguments.clone());
 
-    Ok(ChildWrapper {
-        child: execution,
-        start_time: now,
-    })
+    command
 }
@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Severity {
+    fn parse(level: &str) -> Option<Severity> {
+        match level {
+            "error" | "fatal error" => Some(Self::Error),
+            "warning" => Some(Self::Warning),
+            "note" => Some(Self::Note),
+            "help" => Some(Self::Help),
+            _ => None,
+        }
+    }
+}
+
+/// A compiler message pinned to a position in the job's sources
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// How a build step reports its diagnostics
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiagnosticFormat {
+    /// Output is only streamed, never parsed
+    None,
+    /// `file:line:column: severity: message`, as written by gcc, g++ and go.
+    /// Go omits the severity, so those lines are taken to be errors.
+    Positional,
+    /// Cargo's `--message-format=json`, one JSON object per line on `stdout`
+    Cargo,
+    /// Bun's `severity: message` line, followed by `at file:line:column`
+    Bun,
+}
+
+impl Diagnostic {
+    /// Parses a `file:line:column: [severity: ]message` line
+    pub fn from_positional(line: &str) -> Option<Diagnostic> {
+        let mut parts = line.splitn(4, ':');
+
+        let file = parts.next()?.trim();
+        let line_number = parts.next()?.trim().parse::<u32>().ok()?;
+        let column = parts.next()?.trim().parse::<u32>().ok()?;
+        let rest = parts.next()?.trim();
+
+        let (severity, message) = match rest.split_once(':') {
+            Some((level, message)) => match Severity::parse(level.trim()) {
+                Some(severity) => (severity, message.trim()),
+                None => (Severity::Error, rest),
+            },
+            None => (Severity::Error, rest),
+        };
+
+        if file.is_empty() {
+            return None;
+        }
+
+        Some(Diagnostic {
+            file: file.trim_start_matches("./").to_string(),
+            line: line_number,
+            column,
+            severity,
+            message: message.to_string(),
+        })
+    }
+
+    /// Parses a line of Bun's output. The message preceding a position is
+    /// held in `context` until the position line arrives.
+    pub fn from_bun(line: &str, context: &mut Option<(Severity, String)>) -> Option<Diagnostic> {
+        if let Some((level, message)) = line.split_once(": ") {
+            if let Some(severity) = Severity::parse(level.trim()) {
+                *context = Some((severity, message.trim().to_string()));
+                return None;
+            }
+        }
+
+        let position = line.trim().strip_prefix("at ")?;
+        let mut parts = position.rsplitn(3, ':');
+
+        let column = parts.next()?.parse::<u32>().ok()?;
+        let line_number = parts.next()?.parse::<u32>().ok()?;
+        let file = parts.next()?;
+        let (severity, message) = context.take()?;
+
+        Some(Diagnostic {
+            file: file.trim_start_matches("./").to_string(),
+            line: line_number,
+            column,
+            severity,
+            message,
+        })
+    }
+
+    /// Parses a `compiler-message` line from cargo, returning the diagnostic
+    /// (if it points at a source position) alongside the rendered text.
+    pub fn from_cargo(line: &str) -> Option<(Option<Diagnostic>, String)> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        if value.get("reason")?.as_str()? != "compiler-message" {
+            return None;
+        }
+
+        let message = value.get("message")?;
+        let rendered = message
+            .get("rendered")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        let severity = message
+            .get("level")
+            .and_then(Value::as_str)
+            .and_then(Severity::parse);
+        let span = message
+            .get("spans")
+            .and_then(Value::as_array)
+            .and_then(|spans| {
+                spans
+                    .iter()
+                    .find(|span| span.get("is_primary").and_then(Value::as_bool) == Some(true))
+            });
+
+        let diagnostic = match (severity, span) {
+            (Some(severity), Some(span)) => Some(Diagnostic {
+                file: span.get("file_name")?.as_str()?.to_string(),
+                line: span.get("line_start")?.as_u64()? as u32,
+                column: span.get("column_start")?.as_u64()? as u32,
+                severity,
+                message: message.get("message")?.as_str()?.to_string(),
+            }),
+            _ => None,
+        };
+
+        Some((diagnostic, rendered))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Severity};
+
+    fn position(diagnostic: &Diagnostic) -> (&str, u32, u32, Severity, &str) {
+        (
+            &diagnostic.file,
+            diagnostic.line,
+            diagnostic.column,
+            diagnostic.severity,
+            &diagnostic.message,
+        )
+    }
+
+    #[test]
+    fn parses_positional_lines() {
+        let gcc =
+            Diagnostic::from_positional("main.c:4:5: error: expected ';' before 'return'").unwrap();
+        assert_eq!(
+            position(&gcc),
+            (
+                "main.c",
+                4,
+                5,
+                Severity::Error,
+                "expected ';' before 'return'"
+            )
+        );
+
+        let fatal = Diagnostic::from_positional(
+            "main.cpp:1:10: fatal error: missing.h: No such file or directory",
+        )
+        .unwrap();
+        assert_eq!(
+            position(&fatal),
+            (
+                "main.cpp",
+                1,
+                10,
+                Severity::Error,
+                "missing.h: No such file or directory"
+            )
+        );
+
+        let note = Diagnostic::from_positional("main.c:2:6: note: declared here").unwrap();
+        assert_eq!(note.severity, Severity::Note);
+
+        // Go gives no severity, and its message may hold a colon of its own
+        let go = Diagnostic::from_positional("./main.go:6:2: undefined: total").unwrap();
+        assert_eq!(
+            position(&go),
+            ("main.go", 6, 2, Severity::Error, "undefined: total")
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_positional_lines() {
+        for line in [
+            "",
+            "main.c: In function 'main':",
+            "    4 |     printf(\"hi\")",
+            "main.c:four:5: error: expected ';'",
+            "main.c:4: error: expected ';'",
+            ":4:5: error: expected ';'",
+            "compilation terminated.",
+        ] {
+            assert!(Diagnostic::from_positional(line).is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parses_bun_messages_and_positions() {
+        let mut context = None;
+
+        assert!(Diagnostic::from_bun("1 | const total = ;", &mut context).is_none());
+        assert!(Diagnostic::from_bun("error: Unexpected ;", &mut context).is_none());
+        assert!(context.is_some());
+
+        let diagnostic = Diagnostic::from_bun("    at ./index.ts:1:15", &mut context).unwrap();
+        assert_eq!(
+            position(&diagnostic),
+            ("index.ts", 1, 15, Severity::Error, "Unexpected ;")
+        );
+        assert!(context.is_none());
+    }
+
+    #[test]
+    fn ignores_bun_positions_without_a_message() {
+        let mut context = None;
+        assert!(Diagnostic::from_bun("    at index.ts:1:15", &mut context).is_none());
+
+        Diagnostic::from_bun("warning: Unused variable", &mut context);
+        for line in ["    at index.ts", "    at index.ts:one:15", "at :"] {
+            assert!(
+                Diagnostic::from_bun(line, &mut context).is_none(),
+                "{}",
+                line
+            );
+        }
+
+        // A malformed position leaves the message for the next one
+        let diagnostic = Diagnostic::from_bun("at src/util.ts:3:1", &mut context).unwrap();
+        assert_eq!(
+            position(&diagnostic),
+            ("src/util.ts", 3, 1, Severity::Warning, "Unused variable")
+        );
+    }
+
+    #[test]
+    fn parses_cargo_messages() {
+        let line = r#"{"reason":"compiler-message","package_id":"job 0.1.0 (path+file:///job)","manifest_path":"/job/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"job","src_path":"/job/src/main.rs","edition":"2021","doc":true,"doctest":false,"test":true},"message":{"rendered":"error[E0425]: cannot find value `total` in this scope\n --> src/main.rs:2:20\n","$message_type":"diagnostic","children":[],"code":{"code":"E0425","explanation":null},"level":"error","message":"cannot find value `total` in this scope","spans":[{"byte_end":36,"byte_start":31,"column_end":25,"column_start":20,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":"not found in this scope","line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}"#;
+
+        let (diagnostic, rendered) = Diagnostic::from_cargo(line).unwrap();
+        assert_eq!(
+            position(&diagnostic.unwrap()),
+            (
+                "src/main.rs",
+                2,
+                20,
+                Severity::Error,
+                "cannot find value `total` in this scope"
+            )
+        );
+        assert!(rendered.starts_with("error[E0425]"));
+
+        // A summary has no span, but is still shown
+        let summary = r#"{"reason":"compiler-message","message":{"rendered":"error: aborting due to 1 previous error\n\n","children":[],"code":null,"level":"error","message":"aborting due to 1 previous error","spans":[]}}"#;
+        let (diagnostic, rendered) = Diagnostic::from_cargo(summary).unwrap();
+        assert!(diagnostic.is_none());
+        assert_eq!(rendered, "error: aborting due to 1 previous error\n\n");
+    }
+
+    #[test]
+    fn ignores_other_cargo_lines() {
+        for line in [
+            r#"{"reason":"compiler-artifact","package_id":"job 0.1.0","filenames":["/job/target/release/job"],"fresh":false}"#,
+            r#"{"reason":"build-finished","success":false}"#,
+            r#"{"message":{"rendered":"error"}}"#,
+            "   Compiling job v0.1.0 (/job)",
+            "{\"reason\":\"compiler-message\"",
+            "",
+        ] {
+            assert!(Diagnostic::from_cargo(line).is_none(), "{}", line);
+        }
+    }
+}
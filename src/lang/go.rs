@@ -1,82 +1,107 @@
 use crate::exec::Executor;
-use crate::lang::RuntimeError;
+use crate::lang::{
+    require_entrypoint, write_sources, BuildStep, DiagnosticFormat, LanguageExecutor, RuntimeError,
+};
+use std::path::Path;
 use std::process::Command as LinearCommand;
-use std::{process::Stdio, time::Instant};
-use tokio::process::Command;
-use tokio::sync::MutexGuard;
-
-use super::ChildWrapper;
-
-pub fn run(exec: &MutexGuard<Executor>) -> Result<ChildWrapper, RuntimeError> {
-    // Create File and Fill
-    let file_dir = exec.allocated_dir.to_string();
-    let file_contents: String = exec.src_file.clone();
-
-    match LinearCommand::new("go")
-        .current_dir(&file_dir)
-        .args(["mod", "init", "roadrunner.com/task"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(mut val) => val.wait().unwrap(),
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: 'go mod init roadrunner.com/task' in '{}': {}",
-                file_dir, err
-            )))
-        }
+
+pub const EXECUTOR: LanguageExecutor = LanguageExecutor {
+    prepare,
+    compile,
+    check,
+    execute,
+    toolchain: &["go", "version"],
+    artifacts: &["task"],
+    template: None,
+    reserved: &[],
+    entrypoint: "task.go",
+};
+
+fn prepare(exec: &Executor) -> Result<(), RuntimeError> {
+    if !exec.dependencies.is_empty() {
+        exec.dependencies.mirror("go")?;
+    }
+
+    require_entrypoint(exec)?;
+    write_sources(exec)
+}
+
+/// `go` invocation which only resolves modules from the mirror, if there is one
+fn go(exec: &Executor) -> LinearCommand {
+    let proxy = match exec.dependencies.mirror("go") {
+        Ok(mirror) => format!("file://{}", mirror),
+        Err(_) => "off".to_string(),
     };
 
-    match std::fs::write(format!("{}/task.go", file_dir), file_contents) {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::WriteFailed(err.to_string())),
+    let mut command = LinearCommand::new("go");
+    command
+        .current_dir(exec.workspace())
+        .env("GOPROXY", proxy)
+        .env("GOSUMDB", "off")
+        .env("GOFLAGS", "-mod=mod");
+
+    command
+}
+
+/// Module definition, required by both `go build` and `go vet`, unless the job brought its own
+fn init(exec: &Executor) -> Vec<BuildStep> {
+    let mut steps = vec![];
+
+    if !exec.files.contains_key("go.mod") {
+        let mut init = go(exec);
+        init.args(["mod", "init", "roadrunner.com/task"]);
+
+        steps.push(BuildStep::new(init, DiagnosticFormat::None));
+    }
+
+    if !exec.dependencies.is_empty() {
+        let mut require = go(exec);
+        require.args(["mod", "edit"]).args(
+            exec.dependencies
+                .packages
+                .iter()
+                .map(|(name, version)| format!("-require={}@{}", name, version)),
+        );
+
+        steps.push(BuildStep::new(require, DiagnosticFormat::None));
+    }
+
+    steps
+}
+
+/// Package holding the entrypoint, relative to the module root
+fn package(exec: &Executor) -> String {
+    match Path::new(&exec.entrypoint).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => format!("./{}", parent.display()),
+        _ => ".".to_string(),
     }
+}
 
+fn compile(exec: &Executor) -> Vec<BuildStep> {
     // Compile File
-    let compiler = match LinearCommand::new("go")
-        .current_dir(&file_dir)
-        .args(["build"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(val) => val,
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: 'go run .' in '{}': {}",
-                file_dir, err
-            )))
-        }
-    };
+    let mut compiler = go(exec);
+    compiler.args(["build", "-o", "task", &package(exec)]);
+
+    let mut steps = init(exec);
+    steps.push(BuildStep::new(compiler, DiagnosticFormat::Positional));
+    steps
+}
 
-    let err_output = String::from_utf8(compiler.wait_with_output().unwrap().stderr).unwrap();
-    println!("Running go build: {:?}", err_output);
+fn check(exec: &Executor) -> Vec<BuildStep> {
+    let mut vet = go(exec);
+    vet.args(["vet", "./..."]);
 
-    let now = Instant::now();
-    let new_args = exec.commandline_arguments.arguments.clone();
+    let mut steps = init(exec);
+    steps.push(BuildStep::new(vet, DiagnosticFormat::Positional));
+    steps
+}
 
+fn execute(exec: &Executor) -> LinearCommand {
     // Execute File
-    let execution = match Command::new("./task")
-        .current_dir(&file_dir)
-        .args(new_args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(val) => val,
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: './task' in '{}': {}",
-                file_dir, err
-            )))
-        }
-    };
+    let mut command = LinearCommand::new("./task");
+    command
+        .current_dir(&exec.allocated_dir)
+        .args(exec.commandline_arguments.arguments.clone());
 
-    Ok(ChildWrapper {
-        child: execution,
-        start_time: now,
-    })
+    command
 }
@@ -1,47 +1,91 @@
 use crate::exec::Executor;
-use crate::lang::RuntimeError;
-use std::process::Stdio;
-use std::time::Instant;
-use tokio::process::Command;
-use tokio::sync::MutexGuard;
-
-use super::ChildWrapper;
-
-pub fn run(exec: &MutexGuard<Executor>) -> Result<ChildWrapper, RuntimeError> {
-    // Create File and Fill
-    let file_dir = exec.allocated_dir.to_string();
-    let file_contents: String = exec.src_file.clone();
-
-    match std::fs::write(format!("{}/app.js", file_dir), file_contents) {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::WriteFailed(err.to_string())),
+use crate::lang::{
+    require_entrypoint, write_sources, BuildStep, DiagnosticFormat, LanguageExecutor, RuntimeError,
+};
+use std::process::Command;
+
+pub const EXECUTOR: LanguageExecutor = LanguageExecutor {
+    prepare,
+    compile,
+    check,
+    execute,
+    toolchain: &["bun", "--version"],
+    artifacts: &[],
+    template: None,
+    reserved: &[],
+    entrypoint: "app.js",
+};
+
+fn prepare(exec: &Executor) -> Result<(), RuntimeError> {
+    let file_dir = exec.workspace().to_string();
+
+    require_entrypoint(exec)?;
+    write_sources(exec)?;
+
+    // Written after the sources, so the job cannot replace it
+    if !exec.dependencies.is_empty() {
+        // Packages are tarballs in the mirror, named as `npm pack` would
+        let mirror = exec.dependencies.mirror("npm")?;
+        let dependencies: serde_json::Map<String, serde_json::Value> = exec
+            .dependencies
+            .packages
+            .iter()
+            .map(|(name, version)| {
+                let tarball = format!(
+                    "file:{}/{}-{}.tgz",
+                    mirror,
+                    name.trim_start_matches('@').replace('/', "-"),
+                    version
+                );
+                (name.clone(), serde_json::Value::String(tarball))
+            })
+            .collect();
+
+        let manifest = serde_json::json!({ "dependencies": dependencies });
+        if let Err(err) = std::fs::write(format!("{}/package.json", file_dir), manifest.to_string())
+        {
+            return Err(RuntimeError::WriteFailed(err.to_string()));
+        }
     }
 
-    let new_args = exec.commandline_arguments.arguments.clone();
+    Ok(())
+}
+
+/// Installs the job's dependencies into `node_modules`, if it has any
+fn install(exec: &Executor) -> Vec<BuildStep> {
+    if exec.dependencies.is_empty() {
+        return vec![];
+    }
 
-    let now = Instant::now();
+    let mut installer = Command::new("bun");
+    installer.current_dir(exec.workspace()).args(["install"]);
 
+    vec![BuildStep::new(installer, DiagnosticFormat::None)]
+}
+
+fn compile(exec: &Executor) -> Vec<BuildStep> {
+    install(exec)
+}
+
+fn check(exec: &Executor) -> Vec<BuildStep> {
+    // Bundle into a scratch directory, only the diagnostics are of interest
+    let mut bundler = Command::new("bun");
+    bundler
+        .current_dir(exec.workspace())
+        .args(["build", &exec.entrypoint, "--outdir", ".check"]);
+
+    let mut steps = install(exec);
+    steps.push(BuildStep::new(bundler, DiagnosticFormat::Bun));
+    steps
+}
+
+fn execute(exec: &Executor) -> Command {
     // Execute File
-    let execution = match Command::new("bun")
-        .current_dir(file_dir.clone())
-        .args(["run", "app.js"])
-        .args(new_args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(val) => val,
-        Err(err) => {
-            return Err(RuntimeError::InitializationFailure(format!(
-                "Command: 'bun run app.js' in '{}': {}",
-                file_dir, err
-            )))
-        }
-    };
+    let mut command = Command::new("bun");
+    command
+        .current_dir(&exec.allocated_dir)
+        .args(["run", &exec.entrypoint])
+        .args(exec.commandline_arguments.arguments.clone());
 
-    Ok(ChildWrapper {
-        child: execution,
-        start_time: now,
-    })
+    command
 }
@@ -1,23 +1,258 @@
-use std::process::ExitStatus;
+use std::fs::File;
+use std::os::unix::io::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::pin::Pin;
+use std::process::{Command as LinearCommand, ExitStatus, Stdio};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crate::exec::{Executor, TerminalStream, TerminalStreamType};
+use crate::cache::BuildCache;
+use crate::cgroup::{Cgroups, Usage};
+use crate::exec::{
+    Executor, ExecutorControl, Framing, Mode, OutputStream, ResourceLimits, TerminalStream,
+    TerminalStreamType, WindowSize,
+};
 use crate::lang;
+use crate::lang::{
+    build, collect_artifacts, copy_artifacts, unpack_archive, write_sources, Artifact, BuildStep,
+    Diagnostic, Output, Pty, Templates, END_OF_TRANSMISSION,
+};
+use chrono::Utc;
 use phf::{phf_map, Map};
 use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::Child;
-use tokio::sync::MutexGuard;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, Interest};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, MutexGuard};
+use tokio::time::{sleep_until, timeout};
+
+/// One of a program's standard streams, be it a pipe or its terminal
+type Pipe<T> = Pin<Box<T>>;
+
+/// How long output readers may keep draining once the child has exited
+const OUTPUT_DRAIN_PERIOD: Duration = Duration::from_millis(250);
+
+/// Spawns a job's program as the leader of its own process group, so that
+/// it and anything it forks can be signalled together. On a `terminal`, it
+/// leads a session of its own instead, which makes the terminal its own.
+pub fn spawn(
+    mut command: LinearCommand,
+    resources: ResourceLimits,
+    terminal: Option<File>,
+) -> std::io::Result<Child> {
+    match terminal {
+        Some(terminal) => Pty::attach(&mut command, terminal)?,
+        None => {
+            command.process_group(0);
+        }
+    }
+    resources.apply(&mut command);
+    Command::from(command).spawn()
+}
+
+/// Resizes the program's terminal, should it have one
+fn resize(pty: &Option<Pty>, size: WindowSize) {
+    if let Some(Err(err)) = pty.as_ref().map(|pty| pty.resize(size)) {
+        println!("[TERM]: Could not resize terminal, {}", err);
+    }
+}
+
+/// Resources used by a process and the descendants it waited for, from `wait4`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rusage {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// Peak resident set size, in bytes
+    pub max_rss: u64,
+}
+
+/// Waits for `child` to exit, taking its [`Rusage`] before it is reaped
+pub async fn wait(child: &mut Child) -> std::io::Result<(ExitStatus, Option<Rusage>)> {
+    let rusage = match child.id() {
+        Some(pid) => match exited(pid).await {
+            Ok(()) => rusage(pid),
+            Err(_) => None,
+        },
+        None => None,
+    };
+
+    Ok((child.wait().await?, rusage))
+}
+
+/// Resolves once `pid` has exited, leaving it to be reaped by its [`Child`]
+async fn exited(pid: u32) -> std::io::Result<()> {
+    // SAFETY: `pidfd_open` returns a new descriptor, or -1.
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } as RawFd;
+    if pidfd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: `pidfd` was just opened, and is owned by nothing else.
+    let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd) };
+    let pidfd = AsyncFd::with_interest(pidfd, Interest::READABLE)?;
+    pidfd.readable().await?.retain_ready();
+    Ok(())
+}
+
+/// Usage of `pid`, once it has exited but before it is reaped
+fn rusage(pid: u32) -> Option<Rusage> {
+    let timeval = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+
+    // SAFETY: Both structures are plain data, which the kernel fills in.
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+
+    // SAFETY: The raw syscall takes the `rusage` which glibc's `waitid` omits.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_waitid,
+            libc::P_PID,
+            pid,
+            &mut info as *mut libc::siginfo_t,
+            libc::WEXITED | libc::WNOWAIT | libc::WNOHANG,
+            &mut usage as *mut libc::rusage,
+        )
+    };
+
+    // SAFETY: `info` was zeroed, so `si_pid` is only set once `pid` has exited.
+    if result != 0 || unsafe { info.si_pid() } == 0 {
+        return None;
+    }
+
+    Some(Rusage {
+        user_time: timeval(usage.ru_utime),
+        system_time: timeval(usage.ru_stime),
+        max_rss: usage.ru_maxrss as u64 * 1024,
+    })
+}
+
+/// Name of `signal`, such as `SIGKILL`
+pub fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGSTKFLT => "SIGSTKFLT",
+        libc::SIGCHLD => "SIGCHLD",
+        libc::SIGCONT => "SIGCONT",
+        libc::SIGSTOP => "SIGSTOP",
+        libc::SIGTSTP => "SIGTSTP",
+        libc::SIGTTIN => "SIGTTIN",
+        libc::SIGTTOU => "SIGTTOU",
+        libc::SIGURG => "SIGURG",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGVTALRM => "SIGVTALRM",
+        libc::SIGPROF => "SIGPROF",
+        libc::SIGWINCH => "SIGWINCH",
+        libc::SIGIO => "SIGIO",
+        libc::SIGPWR => "SIGPWR",
+        libc::SIGSYS => "SIGSYS",
+        signal => return format!("SIG{}", signal),
+    };
+
+    name.to_string()
+}
 
-pub struct ChildWrapper {
-    pub child: Child,
-    pub start_time: Instant,
+/// Broadcasts a lifecycle event (`Compiling`, `Running`, ...) for the job
+pub fn announce(exec: &Executor, terminal_type: TerminalStreamType) {
+    let _ = exec
+        .broadcast
+        .0
+        .send(TerminalStream::new_event(terminal_type, exec.nonce.clone()));
 }
 
-#[derive(Clone, Debug, Copy)]
+/// Sends `signal` to every process in the group led by `pid`
+pub fn signal_group(pid: Option<u32>, signal: i32) {
+    if let Some(pid) = pid {
+        // SAFETY: `killpg` has no memory-safety preconditions.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, signal);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Serialize)]
+pub enum TerminationReason {
+    Exited,
+    Cancelled,
+    TimeLimitExceeded,
+    IdleTimeout,
+    CompileError,
+    CompileTimeLimitExceeded,
+    CpuLimitExceeded,
+    FileSizeLimitExceeded,
+    MemoryLimitExceeded,
+    /// Killed once its output reached a cap, see [`OutputLimits`](crate::exec::OutputLimits)
+    OutputLimitExceeded,
+    /// Stopped for a syscall outside of its language's seccomp profile
+    SyscallViolation {
+        syscall: &'static str,
+    },
+}
+
+impl TerminationReason {
+    /// Resource limit which killed the process, if it was signalled for one
+    pub fn from_limit(status: &ExitStatus) -> Option<Self> {
+        match status.signal() {
+            Some(libc::SIGXCPU) => Some(TerminationReason::CpuLimitExceeded),
+            Some(libc::SIGXFSZ) => Some(TerminationReason::FileSizeLimitExceeded),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct ExecutionOutput {
-    pub exit_status: ExitStatus,
+    pub exit_status: Option<ExitStatus>,
+    /// Wall time of the program, or of the build should the program never start
     pub duration: Duration,
+    /// Wall time of the build steps, where any were run
+    pub compile_duration: Option<Duration>,
+    /// Usage of the program and the children it waited for
+    pub rusage: Option<Rusage>,
+    /// Peak memory of the program and its children, in bytes, where measured
+    pub memory_peak: Option<u64>,
+    /// CPU time of the program and its children, where measured
+    pub cpu_time: Option<Duration>,
+    pub reason: TerminationReason,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Whether the build was restored from the cache rather than compiled
+    pub cached: bool,
+    pub artifacts: Vec<Artifact>,
+}
+
+impl ExecutionOutput {
+    /// Output for a job which was cancelled before it was ever started
+    pub fn cancelled() -> Self {
+        ExecutionOutput {
+            exit_status: None,
+            duration: Duration::ZERO,
+            compile_duration: None,
+            rusage: None,
+            memory_peak: None,
+            cpu_time: None,
+            reason: TerminationReason::Cancelled,
+            diagnostics: vec![],
+            cached: false,
+            artifacts: vec![],
+        }
+    }
 }
 
 impl Serialize for ExecutionOutput {
@@ -25,22 +260,80 @@ impl Serialize for ExecutionOutput {
     where
         S: Serializer,
     {
-        let mut seq = serializer.serialize_struct("execution_output", 2)?;
-        seq.serialize_field("exit_status", &self.exit_status.to_string())?;
+        let nanos = |duration: Duration| duration.as_nanos();
+
+        let mut seq = serializer.serialize_struct("execution_output", 15)?;
+        seq.serialize_field(
+            "exit_status",
+            &self.exit_status.map(|status| status.to_string()),
+        )?;
+        seq.serialize_field(
+            "exit_code",
+            &self.exit_status.and_then(|status| status.code()),
+        )?;
+        seq.serialize_field(
+            "signal",
+            &self
+                .exit_status
+                .and_then(|status| status.signal())
+                .map(signal_name),
+        )?;
+        seq.serialize_field(
+            "core_dumped",
+            &self.exit_status.is_some_and(|status| status.core_dumped()),
+        )?;
         seq.serialize_field("duration", &self.duration.as_nanos())?;
+        seq.serialize_field("compile_duration", &self.compile_duration.map(nanos))?;
+        seq.serialize_field(
+            "user_time",
+            &self.rusage.map(|rusage| nanos(rusage.user_time)),
+        )?;
+        seq.serialize_field(
+            "system_time",
+            &self.rusage.map(|rusage| nanos(rusage.system_time)),
+        )?;
+        seq.serialize_field("max_rss", &self.rusage.map(|rusage| rusage.max_rss))?;
+        seq.serialize_field("memory_peak", &self.memory_peak)?;
+        seq.serialize_field("cpu_time", &self.cpu_time.map(nanos))?;
+        seq.serialize_field("reason", &self.reason)?;
+        seq.serialize_field("diagnostics", &self.diagnostics)?;
+        seq.serialize_field("cached", &self.cached)?;
+        seq.serialize_field("artifacts", &self.artifacts)?;
         seq.end()
     }
 }
 
-type LanguageExecutor = fn(&MutexGuard<Executor>) -> Result<ChildWrapper, RuntimeError>;
+/// Hooks through which each language lays out, builds and starts a job
+pub struct LanguageExecutor {
+    /// Writes the job's sources into its [`Executor::workspace`]
+    pub prepare: fn(&Executor) -> Result<(), RuntimeError>,
+    /// Steps run, in order, before the program is executed
+    pub compile: fn(&Executor) -> Vec<BuildStep>,
+    /// Steps reporting diagnostics without producing a program, for `Mode::Check`
+    pub check: fn(&Executor) -> Vec<BuildStep>,
+    /// Command starting the program, its `stdio` is configured by the caller
+    pub execute: fn(&Executor) -> LinearCommand,
+    /// Command printing the toolchain's version, part of the build cache key
+    pub toolchain: &'static [&'static str],
+    /// Files produced by `compile` which `execute` needs, relative to the job's directory.
+    /// Builds are only cached for languages which list some.
+    pub artifacts: &'static [&'static str],
+    /// Seed program for languages whose jobs build in warm workspaces reused
+    /// between them, see [`Templates`]. Sources are laid out in [`Executor::workspace`].
+    pub template: Option<&'static str>,
+    /// Entries at the top of the workspace which belong to the build, a job may not write them
+    pub reserved: &'static [&'static str],
+    /// Path a lone `source` is written to
+    pub entrypoint: &'static str,
+}
 
 static LANGUAGES: Map<&'static str, LanguageExecutor> = phf_map! {
-    "python" => lang::python::run,
-    "javascript" => lang::javascript::run,
-    "rust" => lang::rust::run,
-    "c" => lang::c::run,
-    "cpp" => lang::cpp::run,
-    "go" => lang::go::run,
+    "python" => lang::python::EXECUTOR,
+    "javascript" => lang::javascript::EXECUTOR,
+    "rust" => lang::rust::EXECUTOR,
+    "c" => lang::c::EXECUTOR,
+    "cpp" => lang::cpp::EXECUTOR,
+    "go" => lang::go::EXECUTOR,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +344,17 @@ pub enum RuntimeError {
     WriteFailed(String),
     InitializationFailure(String),
     ParseInput(String),
+    UnknownJob(String),
+    InvalidFile(String),
+    DependencyFailure(String),
+    InvalidArchive(String),
+    /// The job's execution panicked, and was abandoned
+    Panicked(String),
+    UnsupportedLanguage {
+        nonce: String,
+        language: String,
+        valid: Vec<String>,
+    },
 }
 
 impl RuntimeError {
@@ -59,7 +363,7 @@ impl RuntimeError {
     }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Languages {
     Python,
@@ -71,6 +375,19 @@ pub enum Languages {
 }
 
 impl Languages {
+    pub fn executor(&self) -> Option<&'static LanguageExecutor> {
+        LANGUAGES.get(self.as_string())
+    }
+
+    pub const ALL: [Languages; 6] = [
+        Self::Python,
+        Self::Javascript,
+        Self::Rust,
+        Self::Go,
+        Self::C,
+        Self::Cpp,
+    ];
+
     pub fn as_string(&self) -> &str {
         match self {
             Self::Python => "python",
@@ -82,49 +399,297 @@ impl Languages {
         }
     }
 
-    pub fn from_string(language: &str) -> Languages {
-        match language {
-            "python" => Self::Python,
-            "javascript" => Self::Javascript,
-            "rust" => Self::Rust,
-            "go" => Self::Go,
-            "c" => Self::C,
-            "cpp" => Self::Cpp,
-            _ => Self::Python,
+    /// Resolves a language by its name or an alias in any case, or by a file
+    /// extension such as `.py`
+    pub fn from_string(language: &str) -> Option<Languages> {
+        let language = language.trim().to_lowercase();
+
+        match language.as_str() {
+            "python" | "python3" | "py" => Some(Self::Python),
+            "javascript" | "js" | "node" | "nodejs" | "bun" => Some(Self::Javascript),
+            "rust" | "rs" => Some(Self::Rust),
+            "go" | "golang" => Some(Self::Go),
+            "c" => Some(Self::C),
+            "cpp" | "c++" | "cxx" => Some(Self::Cpp),
+            _ => language.strip_prefix('.').and_then(Self::from_extension),
+        }
+    }
+
+    /// Language of a source file with the given extension
+    pub fn from_extension(extension: &str) -> Option<Languages> {
+        match extension.to_lowercase().as_str() {
+            "py" => Some(Self::Python),
+            "js" | "mjs" | "cjs" => Some(Self::Javascript),
+            "rs" => Some(Self::Rust),
+            "go" => Some(Self::Go),
+            "c" | "h" => Some(Self::C),
+            "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => Some(Self::Cpp),
+            _ => None,
+        }
+    }
+
+    /// Resolves the requested `language`, or without one, the language of
+    /// the `entrypoint`. Anything else is refused along with the `nonce`.
+    pub fn resolve(
+        language: Option<&str>,
+        entrypoint: Option<&str>,
+        nonce: &str,
+    ) -> Result<Languages, RuntimeError> {
+        let resolved = match language {
+            Some(language) => Self::from_string(language),
+            None => entrypoint
+                .and_then(|entrypoint| Path::new(entrypoint).extension())
+                .and_then(|extension| extension.to_str())
+                .and_then(Self::from_extension),
+        };
+
+        resolved.ok_or_else(|| RuntimeError::UnsupportedLanguage {
+            nonce: nonce.to_string(),
+            language: language.unwrap_or_default().to_string(),
+            valid: Self::ALL
+                .iter()
+                .map(|language| language.as_string().to_string())
+                .collect(),
+        })
+    }
+
+    pub async fn run(
+        mut exec: MutexGuard<'_, Executor>,
+        cache: &BuildCache,
+        templates: &Templates,
+        cgroups: &Cgroups,
+    ) -> Result<ExecutionOutput, RuntimeError> {
+        let result = Languages::attempt(&mut exec, cache, templates, cgroups).await;
+
+        // However the job ended, failed builds and errors included, it has finished
+        if exec.timings.time_completed.is_none() {
+            exec.timings.time_completed = Some(Utc::now());
         }
+
+        let timings = exec.timings;
+        announce(&exec, TerminalStreamType::Finished { timings });
+
+        result
     }
 
-    pub async fn run(exec: MutexGuard<'_, Executor>) -> Result<ExecutionOutput, RuntimeError> {
-        match LANGUAGES.get(Self::as_string(&exec.language)) {
+    async fn attempt(
+        exec: &mut Executor,
+        cache: &BuildCache,
+        templates: &Templates,
+        cgroups: &Cgroups,
+    ) -> Result<ExecutionOutput, RuntimeError> {
+        match exec.language.executor() {
             Some(executor) => {
-                let mut execution: ChildWrapper = match executor(&exec) {
-                    Ok(val) => val,
-                    Err(err) => return Err(err),
+                let mut control = exec
+                    .control
+                    .1
+                    .take()
+                    .expect("Internal error, could not take control receiver");
+
+                exec.dependencies.validate()?;
+                let artifacts = exec.artifacts.matcher()?;
+                unpack_archive(exec)?;
+
+                // Instructions received while building, replayed once running
+                let mut pending = vec![];
+                let mut diagnostics = vec![];
+                let mut compile_duration = None;
+
+                if exec.mode == Mode::Check {
+                    let workspace = templates.acquire(exec.language).await;
+                    exec.workspace = workspace.as_ref().map(|workspace| workspace.path.clone());
+
+                    (executor.prepare)(exec)?;
+                    announce(exec, TerminalStreamType::Compiling);
+
+                    let steps = (executor.check)(exec);
+                    let build = build(exec, steps, &mut control, &mut pending).await?;
+                    let reason = match build.reason {
+                        TerminationReason::Exited if !build.succeeded() => {
+                            TerminationReason::CompileError
+                        }
+                        reason => reason,
+                    };
+
+                    return Ok(ExecutionOutput {
+                        exit_status: build.exit_status,
+                        duration: build.duration,
+                        compile_duration: Some(build.duration),
+                        rusage: None,
+                        memory_peak: None,
+                        cpu_time: None,
+                        reason,
+                        diagnostics: build.diagnostics,
+                        cached: false,
+                        artifacts: vec![],
+                    });
+                }
+
+                let directory = Path::new(&exec.allocated_dir).to_path_buf();
+                let key = cache.key(exec, executor, &(executor.compile)(exec));
+
+                let restored = key
+                    .as_ref()
+                    .and_then(|key| cache.restore(key, &directory, executor.artifacts));
+                let cached = restored.is_some();
+
+                // Builds for languages with a template run in one of its warm workspaces
+                let workspace = match cached {
+                    true => None,
+                    false => templates.acquire(exec.language).await,
                 };
+                exec.workspace = workspace.as_ref().map(|workspace| workspace.path.clone());
 
-                let child_stdout = execution
-                    .child
-                    .stdout
-                    .take()
-                    .expect("Internal error, could not take stdout");
-                let child_stderr = execution
-                    .child
-                    .stderr
-                    .take()
-                    .expect("Internal error, could not take stderr");
-                let mut child_stdin = execution
-                    .child
-                    .stdin
-                    .take()
-                    .expect("Internal error, could not take stdin");
+                (executor.prepare)(exec)?;
 
-                let stdout_sender = exec.broadcast.0.clone();
-                let stderr_sender = exec.broadcast.0.clone();
-                let stdin_sender = exec.broadcast.0.clone();
+                let steps = (executor.compile)(exec);
+                if let Some(restored) = restored {
+                    println!("[CACHE]: Restored build for {}", exec.id);
+                    diagnostics = restored;
+                } else if !steps.is_empty() {
+                    announce(exec, TerminalStreamType::Compiling);
+
+                    let build = build(exec, steps, &mut control, &mut pending).await?;
+                    if !build.succeeded() {
+                        let reason = match build.reason {
+                            TerminationReason::Exited => TerminationReason::CompileError,
+                            reason => reason,
+                        };
+
+                        return Ok(ExecutionOutput {
+                            exit_status: build.exit_status,
+                            duration: build.duration,
+                            compile_duration: Some(build.duration),
+                            rusage: None,
+                            memory_peak: None,
+                            cpu_time: None,
+                            reason,
+                            diagnostics: build.diagnostics,
+                            cached: false,
+                            artifacts: vec![],
+                        });
+                    }
+
+                    if let Some(workspace) = &workspace {
+                        let workspace = Path::new(&workspace.path);
+                        if let Err(err) = copy_artifacts(workspace, &directory, executor.artifacts)
+                        {
+                            return Err(RuntimeError::InitializationFailure(format!(
+                                "Could not collect artifacts from '{}': {}",
+                                workspace.display(),
+                                err
+                            )));
+                        }
+                    }
+
+                    if let Some(key) = &key {
+                        cache.store(key, &directory, executor.artifacts, &build.diagnostics);
+                    }
+
+                    diagnostics = build.diagnostics;
+                    compile_duration = Some(build.duration);
+                }
+
+                // Free the workspace for the next job before running this one
+                let warm = workspace.is_some();
+                exec.workspace = None;
+                drop(workspace);
 
-                let stdout_nonce = exec.nonce.clone();
-                let stderr_nonce = exec.nonce.clone();
+                // Built elsewhere, yet the program reads its files from its own directory
+                if warm {
+                    write_sources(exec)?;
+                }
+
+                let command = (executor.execute)(exec);
+                let program = command.get_program().to_string_lossy().to_string();
+
+                let command = exec.environment.program(command);
+                let (command, monitor) = exec.sandbox.filter(command, exec.language)?;
+                let mut command = exec.sandbox.wrap(command, &[&exec.allocated_dir])?;
+                if let Some(monitor) = &monitor {
+                    monitor.inherit(&mut command);
+                }
+                exec.environment.base(&mut command);
+
+                let (pty, terminal) = match exec.tty.map(Pty::open).transpose() {
+                    Ok(Some((pty, terminal))) => (Some(pty), Some(terminal)),
+                    Ok(None) => {
+                        command
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped());
+                        (None, None)
+                    }
+                    Err(err) => {
+                        return Err(RuntimeError::InitializationFailure(format!(
+                            "Could not open a pseudo-terminal: {}",
+                            err
+                        )))
+                    }
+                };
+
+                let start_time = Instant::now();
+
+                let cgroup = cgroups.create(exec.id);
+                if let Some(cgroup) = &cgroup {
+                    cgroup.enter(&mut command);
+                }
+
+                let mut child = match spawn(command, exec.limits.run_resources, terminal) {
+                    Ok(child) => child,
+                    Err(err) => {
+                        return Err(RuntimeError::InitializationFailure(format!(
+                            "Command: '{}' in '{}': {}",
+                            program, exec.allocated_dir, err
+                        )))
+                    }
+                };
+
+                exec.timings.time_executed = Some(Utc::now());
+                announce(exec, TerminalStreamType::Running);
+
+                // A terminal is both streams, charged to `stdout`, and takes input too
+                let (child_stdin, outputs): (Pipe<dyn AsyncWrite + Send>, Vec<_>) = match &pty {
+                    Some(pty) => (
+                        Box::pin(pty.clone()),
+                        vec![(
+                            Box::pin(pty.clone()) as Pipe<dyn AsyncRead + Send>,
+                            OutputStream::StandardOutput,
+                        )],
+                    ),
+                    None => (
+                        Box::pin(
+                            child
+                                .stdin
+                                .take()
+                                .expect("Internal error, could not take stdin"),
+                        ),
+                        vec![
+                            (
+                                Box::pin(
+                                    child
+                                        .stdout
+                                        .take()
+                                        .expect("Internal error, could not take stdout"),
+                                ),
+                                OutputStream::StandardOutput,
+                            ),
+                            (
+                                Box::pin(
+                                    child
+                                        .stderr
+                                        .take()
+                                        .expect("Internal error, could not take stderr"),
+                                ),
+                                OutputStream::StandardError,
+                            ),
+                        ],
+                    ),
+                };
+
+                let stdin_sender = exec.broadcast.0.clone();
                 let stdin_nonce = exec.nonce.clone();
+                let tty = pty.is_some();
 
                 // Collate STDIN inputs
                 let input_vec = exec
@@ -140,92 +705,595 @@ impl Languages {
                     .collect::<Vec<&str>>()
                     .join("\n");
 
+                // Dropping the sender closes the child's `stdin`
+                let (stdin_writer, mut stdin_queue) = mpsc::unbounded_channel::<String>();
+                let mut stdin_writer = match exec.interactive {
+                    true => Some(stdin_writer),
+                    false => None,
+                };
+
                 let stdin_thread = tokio::spawn(async move {
-                    match child_stdin.write(input_vec.as_bytes()).await {
+                    let mut child_stdin = child_stdin;
+                    let report =
+                        |error: std::io::Error| match stdin_sender.send(TerminalStream::new(
+                            TerminalStreamType::StandardError,
+                            format!("roadrunner_error: {}", error),
+                            stdin_nonce.clone(),
+                        )) {
+                            Ok(val) => println!("[TERM]: Sent output size {}", val),
+                            Err(err) => println!("[TERM]: Failed to send output {:?}", err),
+                        };
+
+                    match child_stdin.write_all(input_vec.as_bytes()).await {
                         Ok(_) => {
                             println!("Wrote all values.")
                         }
-                        Err(error) => {
-                            match stdin_sender.send(TerminalStream::new(
-                                TerminalStreamType::StandardError,
-                                format!("roadrunner_error: {}", error),
-                                stdin_nonce.clone(),
-                            )) {
-                                Ok(val) => println!("[TERM]: Sent output size {}", val),
-                                Err(err) => println!("[TERM]: Failed to send output {:?}", err),
-                            }
+                        Err(error) => return report(error),
+                    }
+
+                    while let Some(input) = stdin_queue.recv().await {
+                        if let Err(error) = child_stdin.write_all(input.as_bytes()).await {
+                            return report(error);
                         }
+
+                        let _ = stdin_sender.send(TerminalStream::new(
+                            TerminalStreamType::StandardInput,
+                            input,
+                            stdin_nonce.clone(),
+                        ));
                     }
-                });
 
-                let stdout_thread = tokio::spawn(async move {
-                    let mut stdout_lines = BufReader::new(child_stdout).lines();
-                    loop {
-                        if let Some(line) = stdout_lines.next_line().await.unwrap() {
-                            println!("[OKAY_OUTPUT]: {}", line);
-
-                            match stdout_sender.send(TerminalStream::new(
-                                TerminalStreamType::StandardOutput,
-                                line,
-                                stdout_nonce.clone(),
-                            )) {
-                                Ok(val) => println!("[TERM]: Sent output size {}", val),
-                                Err(err) => println!("[TERM]: Failed to send output {:?}", err),
-                            }
+                    // A terminal stays open, so its program is sent an EOF instead
+                    if tty {
+                        if let Err(error) = child_stdin.write_all(END_OF_TRANSMISSION).await {
+                            report(error);
                         }
                     }
                 });
 
-                let stderr_thread = tokio::spawn(async move {
-                    let mut stderr_lines = BufReader::new(child_stderr).lines();
-                    loop {
-                        if let Some(line) = stderr_lines.next_line().await.unwrap() {
-                            println!("[ERROR_OUTPUT]: {}", line);
-
-                            match stderr_sender.send(TerminalStream::new(
-                                TerminalStreamType::StandardError,
-                                line,
-                                stderr_nonce.clone(),
-                            )) {
-                                Ok(val) => println!("[TERM]: Sent output size {}", val),
-                                Err(err) => println!("[TERM]: Failed to send output {:?}", err),
+                // A terminal's output is sent as it arrives, escape sequences and all
+                let framing = match tty {
+                    true => Framing::Chunk,
+                    false => exec.framing,
+                };
+
+                let output = Arc::new(Output::new(
+                    exec.limits.output,
+                    framing,
+                    start_time,
+                    child.id(),
+                    exec.broadcast.0.clone(),
+                    exec.nonce.clone(),
+                ));
+
+                let readers: Vec<_> = outputs
+                    .into_iter()
+                    .map(|(pipe, stream)| {
+                        let output = output.clone();
+                        tokio::spawn(async move { output.forward(pipe, stream).await })
+                    })
+                    .collect();
+
+                let limits = exec.limits;
+                let pid = child.id();
+                let mut cancelled = false;
+
+                for instruction in pending {
+                    match instruction {
+                        ExecutorControl::StandardInput(input) => {
+                            if let Some(writer) = &stdin_writer {
+                                let _ = writer.send(input);
                             }
                         }
+                        ExecutorControl::CloseInput => stdin_writer = None,
+                        ExecutorControl::Resize(size) => resize(&pty, size),
+                        // Acted upon during the build
+                        ExecutorControl::Cancel => {}
                     }
-                });
+                }
 
-                let standard_timeout = Duration::from_secs(5);
+                // Set once a limit is reached and the group has been sent `SIGTERM`
+                let mut terminated: Option<(TerminationReason, Instant)> = None;
+                let mut killed = false;
 
-                loop {
-                    match execution.child.try_wait() {
-                        Ok(optional) => match optional {
-                            Some(exit_status) => {
-                                stdin_thread.abort();
-                                stdout_thread.abort();
-                                stderr_thread.abort();
+                let waiting = wait(&mut child);
+                tokio::pin!(waiting);
 
-                                return Ok(ExecutionOutput {
-                                    exit_status,
-                                    duration: execution.start_time.elapsed(),
-                                });
+                let (exit_status, rusage) = loop {
+                    // Next moment a limit could be reached, given what we know now
+                    let wake = match terminated {
+                        Some((_, signalled)) => signalled + limits.kill_grace_period,
+                        None => {
+                            let idle = limits
+                                .idle_timeout
+                                .map(|limit| start_time + output.last_seen() + limit);
+
+                            let deadline = start_time + limits.time_limit;
+                            idle.map_or(deadline, |idle| idle.min(deadline))
+                        }
+                    };
+
+                    tokio::select! {
+                        status = &mut waiting => match status {
+                            Ok((exit_status, rusage)) => {
+                                break (exec.sandbox.status(exit_status), rusage)
+                            }
+                            Err(err) => {
+                                stdin_thread.abort();
+                                readers.iter().for_each(|reader| reader.abort());
+                                return Err(RuntimeError::Capture(err.to_string()));
+                            }
+                        },
+                        Some(instruction) = control.recv() => match instruction {
+                            ExecutorControl::StandardInput(input) => {
+                                if let Some(writer) = &stdin_writer {
+                                    let _ = writer.send(input);
+                                }
+                            }
+                            ExecutorControl::CloseInput => stdin_writer = None,
+                            ExecutorControl::Resize(size) => resize(&pty, size),
+                            ExecutorControl::Cancel => {
+                                cancelled = true;
+                                signal_group(pid, libc::SIGKILL);
+                            }
+                        },
+                        _ = sleep_until(wake.into()), if !killed => match terminated {
+                            Some(_) => {
+                                signal_group(pid, libc::SIGKILL);
+                                killed = true;
                             }
                             None => {
-                                if execution.start_time.elapsed().ge(&standard_timeout) {
-                                    // Has run for too long, kill it.
-                                    let _ = execution.child.start_kill();
+                                let elapsed = start_time.elapsed();
+                                let idle = output.idle();
+
+                                let reason = if elapsed >= limits.time_limit {
+                                    Some(TerminationReason::TimeLimitExceeded)
+                                } else if limits.idle_timeout.is_some_and(|limit| idle >= limit) {
+                                    Some(TerminationReason::IdleTimeout)
+                                } else {
+                                    None
+                                };
+
+                                if let Some(reason) = reason {
+                                    // Ask nicely first, `SIGKILL` follows after the grace period
+                                    signal_group(pid, libc::SIGTERM);
+                                    terminated = Some((reason, Instant::now()));
                                 }
                             }
                         },
-                        Err(err) => {
-                            stdin_thread.abort();
-                            stdout_thread.abort();
-                            stderr_thread.abort();
-                            return Err(RuntimeError::Capture(err.to_string()));
-                        }
                     }
+                };
+
+                let duration = start_time.elapsed();
+                stdin_thread.abort();
+
+                exec.timings.time_completed = Some(Utc::now());
+
+                // Let the readers drain whatever the child wrote before exiting,
+                // without waiting on descendants which still hold the pipes open.
+                let drain = futures::future::join_all(readers);
+                if timeout(OUTPUT_DRAIN_PERIOD, drain).await.is_err() {
+                    println!("[TERM]: Output still open after exit, discarding.");
                 }
+
+                let usage = cgroup
+                    .as_ref()
+                    .map(|cgroup| cgroup.usage())
+                    .unwrap_or_default();
+                drop(cgroup);
+
+                let violation = monitor.and_then(|monitor| monitor.violation());
+
+                let reason = match (cancelled, terminated, violation) {
+                    (true, _, _) => TerminationReason::Cancelled,
+                    (false, Some((reason, _)), _) => reason,
+                    (false, None, Some(syscall)) => TerminationReason::SyscallViolation { syscall },
+                    (false, None, None) if output.exceeded() => {
+                        TerminationReason::OutputLimitExceeded
+                    }
+                    (false, None, None) if usage.oom_killed => {
+                        TerminationReason::MemoryLimitExceeded
+                    }
+                    (false, None, None) => TerminationReason::from_limit(&exit_status)
+                        .unwrap_or(TerminationReason::Exited),
+                };
+
+                let Usage {
+                    memory_peak,
+                    cpu_time,
+                    ..
+                } = usage;
+
+                Ok(ExecutionOutput {
+                    exit_status: Some(exit_status),
+                    duration,
+                    compile_duration,
+                    rusage,
+                    memory_peak,
+                    cpu_time,
+                    reason,
+                    diagnostics,
+                    cached,
+                    artifacts: collect_artifacts(exec, &artifacts),
+                })
             }
             None => Err(RuntimeError::NoExecutor),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        signal_group, spawn, wait, ExecutionOutput, Languages, RuntimeError, TerminationReason,
+    };
+    use crate::cache::BuildCache;
+    use crate::cgroup::Cgroups;
+    use crate::config::Settings;
+    use crate::exec::{
+        Dependencies, Environment, ExecutorBuilder, Limits, Mode, ResourceLimits, TerminalStream,
+        TerminalStreamType,
+    };
+    use crate::lang::Templates;
+    use std::collections::BTreeMap;
+    use std::io::Write;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::{Path, PathBuf};
+    use std::process::Command as LinearCommand;
+    use tokio::sync::Mutex;
+    use uuid::Uuid;
+
+    fn shell(script: &str) -> LinearCommand {
+        let mut command = LinearCommand::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    /// A server of its own, its directories removed once dropped
+    struct Server {
+        directory: PathBuf,
+        settings: Settings,
+        cache: BuildCache,
+        templates: Templates,
+        cgroups: Cgroups,
+    }
+
+    impl Server {
+        fn new() -> Self {
+            let directory = std::env::temp_dir().join(format!("roadrunner-{}", Uuid::new_v4()));
+            let path = |name: &str| directory.join(name).to_string_lossy().to_string();
+
+            let settings = Settings {
+                template_directory: path("templates"),
+                template_slots: 1,
+                cache_directory: path("cache"),
+                cgroups: false,
+                ..Settings::from_env()
+            };
+
+            Server {
+                cache: BuildCache::open(&settings),
+                templates: Templates::open(&settings),
+                cgroups: Cgroups::open(&settings),
+                directory,
+                settings,
+            }
+        }
+
+        fn job(&self, language: Languages, files: &[(&str, &str)]) -> ExecutorBuilder {
+            let settings = &self.settings;
+            let files = files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                .collect();
+
+            ExecutorBuilder::new()
+                .language(language)
+                .files(files)
+                .environment(Environment {
+                    base: settings.environment(language),
+                    program: BTreeMap::new(),
+                })
+                .limits(Limits {
+                    time_limit: settings.default_time_limit,
+                    compile_time_limit: settings.compile_time_limit,
+                    idle_timeout: None,
+                    kill_grace_period: settings.kill_grace_period,
+                    archive_size: settings.archive_size,
+                    archive_files: settings.archive_files,
+                    artifact_size: settings.artifact_size,
+                    artifact_files: settings.artifact_files,
+                    run_resources: settings.run_resources(language),
+                    compile_resources: settings.compile_resources,
+                    output: settings.output_limits,
+                })
+        }
+
+        /// Runs a job as the pool would, gathering everything it sent
+        async fn run(
+            &self,
+            job: ExecutorBuilder,
+        ) -> (Result<ExecutionOutput, RuntimeError>, Vec<TerminalStream>) {
+            let mut exec = job.build(Uuid::nil());
+            let directory = self.directory.join("jobs").join(exec.id.to_string());
+            exec.allocated_dir = directory.to_string_lossy().to_string();
+            std::fs::create_dir_all(&directory).unwrap();
+
+            let mut events = exec.broadcast.0.subscribe();
+            let exec = Mutex::new(exec);
+            let result = Languages::run(
+                exec.lock().await,
+                &self.cache,
+                &self.templates,
+                &self.cgroups,
+            )
+            .await;
+
+            let mut sent = vec![];
+            while let Ok(event) = events.try_recv() {
+                sent.push(event);
+            }
+
+            (result, sent)
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    /// Everything the program wrote to `stdout`, a line per event
+    fn stdout(events: &[TerminalStream]) -> Vec<&str> {
+        events
+            .iter()
+            .filter(|event| matches!(event.terminal_type, TerminalStreamType::StandardOutput))
+            .filter_map(|event| event.pipe_value.as_deref())
+            .collect()
+    }
+
+    fn ran(events: &[TerminalStream]) -> bool {
+        events
+            .iter()
+            .any(|event| matches!(event.terminal_type, TerminalStreamType::Running))
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_exit_status() {
+        let mut child = spawn(shell("exit 3"), ResourceLimits::default(), None).unwrap();
+        let (status, _) = wait(&mut child).await.unwrap();
+
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn takes_the_usage_before_reaping() {
+        let busy = "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); done";
+        let mut child = spawn(shell(busy), ResourceLimits::default(), None).unwrap();
+
+        let (status, rusage) = wait(&mut child).await.unwrap();
+
+        let rusage = rusage.unwrap();
+        assert!(status.success());
+        assert!(rusage.user_time + rusage.system_time > std::time::Duration::ZERO);
+        assert!(rusage.max_rss > 0);
+    }
+
+    #[tokio::test]
+    async fn signals_the_whole_group() {
+        let command = shell("sleep 30 & wait");
+        let mut child = spawn(command, ResourceLimits::default(), None).unwrap();
+        let pid = child.id().unwrap() as libc::pid_t;
+
+        // SAFETY: `getpgid` has no memory-safety preconditions.
+        assert_eq!(unsafe { libc::getpgid(pid) }, pid);
+
+        signal_group(Some(pid as u32), libc::SIGKILL);
+        let (status, _) = wait(&mut child).await.unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+
+        // The backgrounded `sleep` went with it, once whoever inherited it reaps it
+        // SAFETY: As above, `killpg` with no signal only checks for the group.
+        let mut attempts = 0;
+        while unsafe { libc::killpg(pid, 0) } == 0 && attempts < 100 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            attempts += 1;
+        }
+        assert_ne!(unsafe { libc::killpg(pid, 0) }, 0);
+    }
+
+    #[test]
+    fn resolves_names_and_aliases() {
+        for (name, language) in [
+            ("python", Languages::Python),
+            ("Python3", Languages::Python),
+            (" js ", Languages::Javascript),
+            ("c++", Languages::Cpp),
+            (".rs", Languages::Rust),
+            ("golang", Languages::Go),
+        ] {
+            assert_eq!(Languages::resolve(Some(name), None, "n").unwrap(), language);
+        }
+    }
+
+    #[test]
+    fn infers_from_the_entrypoint() {
+        let language = Languages::resolve(None, Some("src/main.CC"), "n").unwrap();
+        assert_eq!(language, Languages::Cpp);
+
+        // An explicit language wins over the entrypoint's extension
+        let language = Languages::resolve(Some("python"), Some("app.js"), "n").unwrap();
+        assert_eq!(language, Languages::Python);
+    }
+
+    #[test]
+    fn refuses_unknown_languages() {
+        for (language, entrypoint) in [
+            (Some("cobol"), None),
+            (None, Some("main.txt")),
+            (None, None),
+        ] {
+            match Languages::resolve(language, entrypoint, "n") {
+                Err(RuntimeError::UnsupportedLanguage { nonce, valid, .. }) => {
+                    assert_eq!(nonce, "n");
+                    assert_eq!(valid.len(), Languages::ALL.len());
+                }
+                other => panic!("{:?} resolved to {:?}", language, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_without_running() {
+        let server = Server::new();
+        let job = server
+            .job(Languages::Python, &[("main.py", "print('ran')\n")])
+            .mode(Mode::Check);
+
+        let (output, events) = server.run(job).await;
+        let output = output.unwrap();
+
+        assert_eq!(output.reason, TerminationReason::Exited);
+        assert!(output.diagnostics.is_empty());
+        assert!(!ran(&events));
+        assert!(stdout(&events).is_empty());
+    }
+
+    #[tokio::test]
+    async fn checks_report_diagnostics() {
+        let server = Server::new();
+        let source = "total = 1\nprint(total +)\n";
+        let job = server
+            .job(Languages::Python, &[("main.py", source)])
+            .mode(Mode::Check);
+
+        let (output, events) = server.run(job).await;
+        let output = output.unwrap();
+
+        assert_eq!(output.reason, TerminationReason::CompileError);
+        assert!(!ran(&events));
+
+        let diagnostic = &output.diagnostics[0];
+        assert_eq!((diagnostic.file.as_str(), diagnostic.line), ("main.py", 2));
+    }
+
+    #[tokio::test]
+    async fn checks_compiled_languages() {
+        let server = Server::new();
+        let job = server
+            .job(
+                Languages::C,
+                &[("main.c", "int main() { return total; }\n")],
+            )
+            .mode(Mode::Check);
+
+        let (output, events) = server.run(job).await;
+        let output = output.unwrap();
+
+        assert_eq!(output.reason, TerminationReason::CompileError);
+        assert!(!ran(&events));
+        assert!(output
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.file == "main.c" && diagnostic.line == 1));
+    }
+
+    fn tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn warm_builds_keep_their_data_files() {
+        let server = Server::new();
+        let source = r#"fn main() {
+            let data = std::fs::read_to_string("data/input.txt").unwrap();
+            let packed = std::fs::read_to_string("packed.txt").unwrap();
+            println!("{} {}", data.trim(), packed.trim());
+        }"#;
+        let job = |data: &str| {
+            server
+                .job(
+                    Languages::Rust,
+                    &[("src/main.rs", source), ("data/input.txt", data)],
+                )
+                .archive(Some(tar(&[("packed.txt", "packed")])))
+        };
+
+        // Built in the workspace from scratch, then again once it is warm,
+        // then restored from the cache without a workspace at all
+        for (data, cached) in [("cold", false), ("warm", false), ("warm", true)] {
+            let (output, events) = server.run(job(data)).await;
+            let output = output.unwrap();
+
+            assert_eq!(output.cached, cached);
+            assert_eq!(output.exit_status.and_then(|status| status.code()), Some(0));
+            assert_eq!(stdout(&events), [format!("{} packed", data)]);
+        }
+    }
+
+    /// Writes a wheel of the `greeting` package into a pip mirror
+    fn wheel(mirror: &Path) {
+        std::fs::create_dir_all(mirror).unwrap();
+        let file = std::fs::File::create(mirror.join("greeting-1.0-py3-none-any.whl")).unwrap();
+        let mut wheel = zip::ZipWriter::new(file);
+
+        for (path, contents) in [
+            ("greeting/__init__.py", "WORD = 'hello'\n"),
+            (
+                "greeting-1.0.dist-info/METADATA",
+                "Metadata-Version: 2.1\nName: greeting\nVersion: 1.0\n",
+            ),
+            (
+                "greeting-1.0.dist-info/WHEEL",
+                "Wheel-Version: 1.0\nRoot-Is-Purelib: true\nTag: py3-none-any\n",
+            ),
+            ("greeting-1.0.dist-info/RECORD", ""),
+        ] {
+            wheel
+                .start_file(path, zip::write::FileOptions::default())
+                .unwrap();
+            wheel.write_all(contents.as_bytes()).unwrap();
+        }
+
+        wheel.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn installs_dependencies_from_the_mirror() {
+        let server = Server::new();
+        let mirror = server.directory.join("mirror");
+        wheel(&mirror.join("pip"));
+
+        let source = "import greeting\nprint(greeting.WORD)\n";
+        let job = |mirror: Option<&Path>| {
+            server
+                .job(Languages::Python, &[("main.py", source)])
+                .dependencies(Dependencies {
+                    packages: BTreeMap::from([("greeting".to_string(), "1.0".to_string())]),
+                    mirror: mirror.map(|mirror| mirror.to_string_lossy().to_string()),
+                })
+        };
+
+        let (output, events) = server.run(job(Some(&mirror))).await;
+        assert_eq!(
+            output.unwrap().exit_status.and_then(|status| status.code()),
+            Some(0)
+        );
+        assert_eq!(stdout(&events), ["hello"]);
+
+        // Without a mirror they are refused, rather than fetched from the network
+        let (output, events) = server.run(job(None)).await;
+        assert!(matches!(output, Err(RuntimeError::DependencyFailure(_))));
+        assert!(!ran(&events));
+    }
+}
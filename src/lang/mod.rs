@@ -1,6 +1,22 @@
+pub use archive::*;
+pub use build::*;
+pub use collect::*;
+pub use diagnostic::*;
 pub use language::*;
+pub use output::*;
+pub use pty::*;
+pub use sources::*;
+pub use template::*;
 
+mod archive;
+mod build;
+mod collect;
+mod diagnostic;
 mod language;
+mod output;
+mod pty;
+mod sources;
+mod template;
 
 mod c;
 mod cpp;
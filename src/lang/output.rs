@@ -0,0 +1,429 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::exec::{
+    Framing, OutputLimit, OutputLimits, OutputPolicy, OutputStream, TerminalStream,
+    TerminalStreamType,
+};
+use crate::lang::signal_group;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::broadcast::Sender;
+
+/// A program's output, shared by its `stdout` and `stderr` readers, which
+/// forward it by line or chunk within the job's [`OutputLimits`].
+pub struct Output {
+    limits: OutputLimits,
+    framing: Framing,
+    /// Bytes and lines sent from both streams
+    sent: Mutex<(u64, u64)>,
+    start_time: Instant,
+    /// Milliseconds since `start_time` at which output was last seen
+    last_seen: AtomicU64,
+    /// Whether the program was killed for its output
+    exceeded: AtomicBool,
+    pid: Option<u32>,
+    sender: Sender<TerminalStream>,
+    nonce: Option<String>,
+}
+
+impl Output {
+    pub fn new(
+        limits: OutputLimits,
+        framing: Framing,
+        start_time: Instant,
+        pid: Option<u32>,
+        sender: Sender<TerminalStream>,
+        nonce: Option<String>,
+    ) -> Self {
+        Output {
+            limits,
+            framing,
+            sent: Mutex::new((0, 0)),
+            start_time,
+            last_seen: AtomicU64::new(0),
+            exceeded: AtomicBool::new(false),
+            pid,
+            sender,
+            nonce,
+        }
+    }
+
+    /// Time since the program last wrote anything, sent or not
+    pub fn idle(&self) -> Duration {
+        self.start_time.elapsed().saturating_sub(self.last_seen())
+    }
+
+    /// Time since the program started at which it last wrote anything
+    pub fn last_seen(&self) -> Duration {
+        Duration::from_millis(self.last_seen.load(Ordering::SeqCst))
+    }
+
+    /// Whether the program was killed for reaching a cap
+    pub fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::SeqCst)
+    }
+
+    /// Reads `pipe` to its end, sending its output until a cap is reached.
+    /// The pipe is drained all the same, so the program never blocks on it.
+    pub async fn forward<R: AsyncRead + Unpin>(&self, pipe: R, stream: OutputStream) {
+        let mut reader = BufReader::new(pipe);
+        // A line not yet ended, or a character cut short at the end of a chunk
+        let mut pending: Vec<u8> = vec![];
+        // Bytes and lines sent from this stream alone
+        let mut sent = (0u64, 0u64);
+        let mut truncated = false;
+
+        loop {
+            let buffer = match reader.fill_buf().await {
+                Ok(buffer) if !buffer.is_empty() => buffer,
+                _ => break,
+            };
+
+            self.last_seen.store(
+                self.start_time.elapsed().as_millis() as u64,
+                Ordering::SeqCst,
+            );
+
+            let consumed = match self.framing {
+                Framing::Line => {
+                    let (chunk, complete) = match buffer.iter().position(|byte| *byte == b'\n') {
+                        Some(end) => (&buffer[..end], true),
+                        None => (buffer, false),
+                    };
+
+                    if !truncated {
+                        pending.extend_from_slice(chunk);
+
+                        // A line is only sent whole, so a partial one must fit already
+                        let length = pending.len() as u64 + complete as u64;
+                        match self.reserve(&mut sent, length, 1, complete) {
+                            Ok(()) if complete => {
+                                self.send(stream, &pending);
+                                pending.clear();
+                            }
+                            Ok(()) => {}
+                            Err(limit) => {
+                                truncated = true;
+                                pending = vec![];
+                                self.truncate(stream, limit);
+                            }
+                        }
+                    }
+
+                    chunk.len() + complete as usize
+                }
+                Framing::Chunk => {
+                    if !truncated {
+                        pending.extend_from_slice(buffer);
+
+                        let chunk: Vec<u8> = pending.drain(..complete(&pending)).collect();
+                        let lines = chunk.iter().filter(|byte| **byte == b'\n').count() as u64;
+                        match self.reserve(&mut sent, chunk.len() as u64, lines, true) {
+                            Ok(()) if chunk.is_empty() => {}
+                            Ok(()) => self.send(stream, &chunk),
+                            Err(limit) => {
+                                truncated = true;
+                                pending = vec![];
+                                self.truncate(stream, limit);
+                            }
+                        }
+                    }
+
+                    buffer.len()
+                }
+            };
+
+            reader.consume(consumed);
+        }
+
+        // Whatever the program left unfinished, an unended line or a cut character
+        if !truncated && !pending.is_empty() {
+            let lines = match self.framing {
+                Framing::Line => 1,
+                Framing::Chunk => 0,
+            };
+            match self.reserve(&mut sent, pending.len() as u64, lines, true) {
+                Ok(()) => self.send(stream, &pending),
+                Err(limit) => self.truncate(stream, limit),
+            }
+        }
+    }
+
+    /// Checks `length` bytes and `lines` lines against the caps, counting
+    /// them only if `commit`. Returns the first cap they would exceed.
+    fn reserve(
+        &self,
+        stream: &mut (u64, u64),
+        length: u64,
+        lines: u64,
+        commit: bool,
+    ) -> Result<(), OutputLimit> {
+        let over = |cap: Option<u64>, value: u64| cap.is_some_and(|cap| value > cap);
+
+        if over(self.limits.stream_bytes, stream.0 + length) {
+            return Err(OutputLimit::StreamBytes);
+        }
+        if over(self.limits.stream_lines, stream.1 + lines) {
+            return Err(OutputLimit::StreamLines);
+        }
+
+        let mut job = self.sent.lock().unwrap();
+        if over(self.limits.job_bytes, job.0 + length) {
+            return Err(OutputLimit::JobBytes);
+        }
+        if over(self.limits.job_lines, job.1 + lines) {
+            return Err(OutputLimit::JobLines);
+        }
+
+        if commit {
+            *job = (job.0 + length, job.1 + lines);
+            *stream = (stream.0 + length, stream.1 + lines);
+        }
+        Ok(())
+    }
+
+    fn send(&self, stream: OutputStream, output: &[u8]) {
+        let terminal_type = match stream {
+            OutputStream::StandardOutput => {
+                println!("[OKAY_OUTPUT]: {}", String::from_utf8_lossy(output));
+                TerminalStreamType::StandardOutput
+            }
+            OutputStream::StandardError => {
+                println!("[ERROR_OUTPUT]: {}", String::from_utf8_lossy(output));
+                TerminalStreamType::StandardError
+            }
+        };
+
+        match self.sender.send(TerminalStream::new_bytes(
+            terminal_type,
+            output,
+            self.nonce.clone(),
+        )) {
+            Ok(val) => println!("[TERM]: Sent output size {}", val),
+            Err(err) => println!("[TERM]: Failed to send output {:?}", err),
+        }
+    }
+
+    /// Announces that `stream` reached `limit`, acting on the job's policy
+    fn truncate(&self, stream: OutputStream, limit: OutputLimit) {
+        println!("[TERM]: Output reached {:?}, truncating", limit);
+        let _ = self.sender.send(TerminalStream::new_event(
+            TerminalStreamType::Truncated { stream, limit },
+            self.nonce.clone(),
+        ));
+
+        if self.limits.policy == OutputPolicy::Kill && !self.exceeded.swap(true, Ordering::SeqCst) {
+            signal_group(self.pid, libc::SIGKILL);
+        }
+    }
+}
+
+/// Length of `bytes` short of a character cut off at its end, which waits
+/// for the rest of it. Output which is not UTF-8 at all is sent as it is.
+fn complete(bytes: &[u8]) -> usize {
+    match std::str::from_utf8(bytes) {
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        _ => bytes.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete, Output};
+    use crate::exec::{
+        Encoding, Framing, OutputLimit, OutputLimits, OutputPolicy, OutputStream, ResourceLimits,
+        TerminalStream, TerminalStreamType,
+    };
+    use crate::lang::spawn;
+    use std::collections::VecDeque;
+    use std::os::unix::process::ExitStatusExt;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Instant;
+    use tokio::io::{AsyncRead, ReadBuf};
+    use tokio::sync::broadcast;
+
+    /// A pipe which yields each of its writes in a read of its own
+    struct Writes(VecDeque<&'static [u8]>);
+
+    impl AsyncRead for Writes {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            if let Some(write) = self.0.pop_front() {
+                buf.put_slice(write);
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    const UNLIMITED: OutputLimits = OutputLimits {
+        stream_bytes: None,
+        stream_lines: None,
+        job_bytes: None,
+        job_lines: None,
+        policy: OutputPolicy::Discard,
+    };
+
+    async fn forward(
+        limits: OutputLimits,
+        framing: Framing,
+        writes: &[&'static [u8]],
+    ) -> Vec<TerminalStream> {
+        let (sender, mut receiver) = broadcast::channel(64);
+        let output = Output::new(limits, framing, Instant::now(), None, sender, None);
+
+        let pipe = Writes(writes.iter().copied().collect());
+        output.forward(pipe, OutputStream::StandardOutput).await;
+
+        let mut sent = vec![];
+        while let Ok(stream) = receiver.try_recv() {
+            sent.push(stream);
+        }
+        sent
+    }
+
+    fn values(sent: &[TerminalStream]) -> Vec<&str> {
+        sent.iter()
+            .filter_map(|stream| stream.pipe_value.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn holds_back_a_character_cut_short() {
+        assert_eq!(complete(b"ab\xc3"), 2);
+        assert_eq!(complete(b"ab\xe2\x82"), 2);
+        assert_eq!(complete(b"ab\xc3\xa9"), 4);
+        assert_eq!(complete(b"ab\xff"), 3);
+    }
+
+    #[tokio::test]
+    async fn sends_whole_lines() {
+        let sent = forward(UNLIMITED, Framing::Line, &[b"one\ntw", b"o\nthree"]).await;
+        assert_eq!(values(&sent), ["one", "two", "three"]);
+    }
+
+    #[tokio::test]
+    async fn joins_a_character_split_between_chunks() {
+        let sent = forward(UNLIMITED, Framing::Chunk, &[b"caf\xc3", b"\xa9\n"]).await;
+        assert_eq!(values(&sent), ["caf", "\u{e9}\n"]);
+        assert!(sent.iter().all(|stream| stream.encoding.is_none()));
+    }
+
+    #[tokio::test]
+    async fn encodes_output_which_is_not_utf8() {
+        let sent = forward(UNLIMITED, Framing::Chunk, &[b"\xff\xfe"]).await;
+        assert_eq!(values(&sent), ["//4="]);
+        assert_eq!(sent[0].encoding, Some(Encoding::Base64));
+    }
+
+    #[tokio::test]
+    async fn truncates_at_a_cap() {
+        let limits = OutputLimits {
+            stream_bytes: Some(4),
+            ..UNLIMITED
+        };
+        let sent = forward(limits, Framing::Chunk, &[b"abc", b"def", b"ghi"]).await;
+
+        assert_eq!(values(&sent), ["abc"]);
+        assert!(matches!(
+            sent[1].terminal_type,
+            TerminalStreamType::Truncated {
+                stream: OutputStream::StandardOutput,
+                limit: OutputLimit::StreamBytes,
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn discards_the_rest_of_a_stream() {
+        let limits = OutputLimits {
+            stream_lines: Some(2),
+            ..UNLIMITED
+        };
+        let sent = forward(
+            limits,
+            Framing::Line,
+            &[
+                b"one
+two
+three
+",
+                b"four
+",
+            ],
+        )
+        .await;
+
+        assert_eq!(values(&sent), ["one", "two"]);
+        assert_eq!(sent.len(), 3);
+        assert!(matches!(
+            sent[2].terminal_type,
+            TerminalStreamType::Truncated {
+                limit: OutputLimit::StreamLines,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn caps_both_streams_together() {
+        let limits = OutputLimits {
+            job_bytes: Some(6),
+            ..UNLIMITED
+        };
+        let (sender, mut receiver) = broadcast::channel(64);
+        let output = Output::new(limits, Framing::Chunk, Instant::now(), None, sender, None);
+
+        let stdout = Writes([b"abcd" as &[u8]].into_iter().collect());
+        let stderr = Writes([b"ef" as &[u8], b"gh"].into_iter().collect());
+        output.forward(stdout, OutputStream::StandardOutput).await;
+        output.forward(stderr, OutputStream::StandardError).await;
+
+        let mut sent = vec![];
+        while let Ok(stream) = receiver.try_recv() {
+            sent.push(stream);
+        }
+        assert_eq!(values(&sent), ["abcd", "ef"]);
+        assert!(matches!(
+            sent[2].terminal_type,
+            TerminalStreamType::Truncated {
+                stream: OutputStream::StandardError,
+                limit: OutputLimit::JobBytes,
+            }
+        ));
+        assert!(!output.exceeded());
+    }
+
+    #[tokio::test]
+    async fn kills_the_program_at_a_cap() {
+        let mut sleep = std::process::Command::new("sleep");
+        sleep.arg("30");
+        let mut child = spawn(sleep, ResourceLimits::default(), None).unwrap();
+
+        let limits = OutputLimits {
+            stream_bytes: Some(2),
+            policy: OutputPolicy::Kill,
+            ..UNLIMITED
+        };
+        let (sender, _receiver) = broadcast::channel(64);
+        let output = Output::new(
+            limits,
+            Framing::Chunk,
+            Instant::now(),
+            child.id(),
+            sender,
+            None,
+        );
+
+        let pipe = Writes([b"abc" as &[u8]].into_iter().collect());
+        output.forward(pipe, OutputStream::StandardOutput).await;
+
+        assert!(output.exceeded());
+        let status = child.wait().await.unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
+}
@@ -0,0 +1,280 @@
+use std::ffi::CStr;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::pin::Pin;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use crate::exec::WindowSize;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Sent in place of closing `stdin`, which a terminal cannot do
+pub const END_OF_TRANSMISSION: &[u8] = b"\x04";
+
+/// The server's side of a program's pseudo-terminal. Reading it yields the
+/// program's output, and whatever is written to it becomes the program's input.
+#[derive(Clone)]
+pub struct Pty {
+    master: Arc<AsyncFd<OwnedFd>>,
+}
+
+impl Pty {
+    /// Opens a pseudo-terminal of `size`, returning it with the program's side
+    pub fn open(size: WindowSize) -> io::Result<(Pty, File)> {
+        let check = |result: libc::c_int| match result {
+            -1 => Err(io::Error::last_os_error()),
+            result => Ok(result),
+        };
+
+        // SAFETY: `posix_openpt` returns a new descriptor, which is ours to own.
+        let master = unsafe {
+            let fd = check(libc::posix_openpt(
+                libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+            ))?;
+            OwnedFd::from_raw_fd(fd)
+        };
+
+        let mut name = [0 as libc::c_char; 64];
+        // SAFETY: `master` is an open pseudo-terminal, and `name` is as long as
+        // we say it is.
+        unsafe {
+            check(libc::grantpt(master.as_raw_fd()))?;
+            check(libc::unlockpt(master.as_raw_fd()))?;
+            match libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()) {
+                0 => {}
+                err => return Err(io::Error::from_raw_os_error(err)),
+            }
+        }
+
+        // SAFETY: `ptsname_r` wrote a NUL-terminated path, and `open` returns a
+        // new descriptor.
+        let slave = unsafe {
+            let path = CStr::from_ptr(name.as_ptr());
+            let fd = check(libc::open(
+                path.as_ptr(),
+                libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+            ))?;
+            File::from_raw_fd(fd)
+        };
+
+        // SAFETY: `fcntl` only changes the flags of a descriptor we own.
+        unsafe {
+            let flags = check(libc::fcntl(master.as_raw_fd(), libc::F_GETFL))?;
+            check(libc::fcntl(
+                master.as_raw_fd(),
+                libc::F_SETFL,
+                flags | libc::O_NONBLOCK,
+            ))?;
+        }
+
+        let pty = Pty {
+            master: Arc::new(AsyncFd::new(master)?),
+        };
+        pty.resize(size)?;
+
+        Ok((pty, slave))
+    }
+
+    /// Resizes the terminal, which sends its program `SIGWINCH`
+    pub fn resize(&self, size: WindowSize) -> io::Result<()> {
+        let window = libc::winsize {
+            ws_row: size.rows,
+            ws_col: size.columns,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+
+        // SAFETY: `TIOCSWINSZ` reads a `winsize`, which outlives the call.
+        match unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &window) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes `slave` the standard streams and controlling terminal of
+    /// `command`, which leads a new session, and with it a process group.
+    pub fn attach(command: &mut Command, slave: File) -> io::Result<()> {
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+
+        // SAFETY: only `setsid` and `ioctl` are called, which are async-signal-safe.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl AsyncRead for Pty {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.master.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+
+            // SAFETY: `unfilled` is valid for writes of its whole length.
+            let read = guard.try_io(|master| {
+                match unsafe {
+                    libc::read(
+                        master.as_raw_fd(),
+                        unfilled.as_mut_ptr() as *mut libc::c_void,
+                        unfilled.len(),
+                    )
+                } {
+                    -1 => Err(io::Error::last_os_error()),
+                    read => Ok(read as usize),
+                }
+            });
+
+            match read {
+                Ok(Ok(read)) => {
+                    buf.advance(read);
+                    return Poll::Ready(Ok(()));
+                }
+                // Every copy of the program's side has been closed
+                Ok(Err(err)) if err.raw_os_error() == Some(libc::EIO) => {
+                    return Poll::Ready(Ok(()))
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Pty {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.master.poll_write_ready(cx))?;
+
+            // SAFETY: `buf` is valid for reads of its whole length.
+            let written = guard.try_io(|master| {
+                match unsafe {
+                    libc::write(
+                        master.as_raw_fd(),
+                        buf.as_ptr() as *const libc::c_void,
+                        buf.len(),
+                    )
+                } {
+                    -1 => Err(io::Error::last_os_error()),
+                    written => Ok(written as usize),
+                }
+            });
+
+            match written {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pty, END_OF_TRANSMISSION};
+    use crate::exec::{ResourceLimits, WindowSize};
+    use crate::lang::spawn;
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+    use std::process::Command;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Size of the terminal as its program sees it
+    fn size(slave: &File) -> WindowSize {
+        // SAFETY: A zeroed `winsize` is valid, and `TIOCGWINSZ` fills it in.
+        let mut window: libc::winsize = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCGWINSZ, &mut window) },
+            0
+        );
+
+        WindowSize {
+            rows: window.ws_row,
+            columns: window.ws_col,
+        }
+    }
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    #[tokio::test]
+    async fn opens_and_resizes_the_terminal() {
+        let opened = WindowSize {
+            rows: 24,
+            columns: 80,
+        };
+        let resized = WindowSize {
+            rows: 50,
+            columns: 132,
+        };
+
+        let (pty, slave) = Pty::open(opened).unwrap();
+        assert_eq!(size(&slave), opened);
+
+        pty.resize(resized).unwrap();
+        assert_eq!(size(&slave), resized);
+    }
+
+    #[tokio::test]
+    async fn carries_input_and_output() {
+        let (mut pty, slave) = Pty::open(WindowSize::default()).unwrap();
+        let command = shell("read line; echo \"got $line\"; [ -t 0 ] && echo tty");
+        let mut child = spawn(command, ResourceLimits::default(), Some(slave)).unwrap();
+
+        pty.write_all(b"hi\n").await.unwrap();
+
+        // Ends once the program, the last holder of its side, has exited
+        let mut output = vec![];
+        pty.read_to_end(&mut output).await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        // The terminal echoes what was typed, and ends lines with `\r\n`
+        assert!(output.contains("hi\r\n"), "{:?}", output);
+        assert!(output.contains("got hi\r\ntty\r\n"), "{:?}", output);
+        assert!(child.wait().await.unwrap().success());
+    }
+
+    #[tokio::test]
+    async fn ends_input_with_end_of_transmission() {
+        let (mut pty, slave) = Pty::open(WindowSize::default()).unwrap();
+        let mut child = spawn(shell("cat"), ResourceLimits::default(), Some(slave)).unwrap();
+
+        pty.write_all(b"one\n").await.unwrap();
+        pty.write_all(END_OF_TRANSMISSION).await.unwrap();
+
+        let mut output = vec![];
+        pty.read_to_end(&mut output).await.unwrap();
+        assert!(String::from_utf8_lossy(&output).contains("one\r\none\r\n"));
+        assert!(child.wait().await.unwrap().success());
+    }
+}
@@ -1,53 +1,121 @@
 use crate::exec::Executor;
-use crate::lang::RuntimeError;
-use std::{process::Stdio, time::Instant};
-use tokio::{process::Command, sync::MutexGuard};
-
-use super::ChildWrapper;
-
-pub fn run(exec: &MutexGuard<Executor>) -> Result<ChildWrapper, RuntimeError> {
-    // Create File and Fill
-    let file_dir = exec.allocated_dir.to_string();
-
-    let print_override = "from __future__ import print_function
-try:
-    # python2
-    import __builtin__
-except ImportError:
-    # python3
-    import builtins as __builtin__
-    
-def print(*args, **kwargs):
-    return __builtin__.print(*args, **kwargs, flush=True)\n";
-
-    let file_contents: String = format!(
-        // Provide default print() which will flush by default.
-        "{}{}",
-        print_override,
-        exec.src_file.clone()
-    );
-
-    match std::fs::write(format!("{}/{}.py", file_dir, exec.id), file_contents) {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::WriteFailed(err.to_string())),
+use crate::lang::{
+    require_entrypoint, sources_with, write_sources, BuildStep, DiagnosticFormat, LanguageExecutor,
+    RuntimeError,
+};
+use std::process::Command;
+
+pub const EXECUTOR: LanguageExecutor = LanguageExecutor {
+    prepare,
+    compile,
+    check,
+    execute,
+    toolchain: &["python3", "--version"],
+    artifacts: &[],
+    template: None,
+    reserved: &[],
+    entrypoint: "main.py",
+};
+
+/// Byte-compiles each file, reporting syntax errors as `file:line:column: error: message`
+const PY_COMPILE: &str = "import py_compile, sys
+failed = False
+for path in sys.argv[1:]:
+    try:
+        py_compile.compile(path, doraise=True)
+    except py_compile.PyCompileError as err:
+        value = err.exc_value
+        print('{}:{}:{}: error: {}'.format(
+            getattr(value, 'filename', None) or path,
+            getattr(value, 'lineno', None) or 1,
+            getattr(value, 'offset', None) or 1,
+            getattr(value, 'msg', None) or err.msg,
+        ))
+        failed = True
+sys.exit(1 if failed else 0)";
+
+/// Directory the job's dependencies are installed into
+const PACKAGES: &str = ".packages";
+
+fn prepare(exec: &Executor) -> Result<(), RuntimeError> {
+    let file_dir = exec.workspace().to_string();
+
+    // Written as-is, so that line numbers match the client's sources
+    require_entrypoint(exec)?;
+    write_sources(exec)?;
+
+    // Written after the sources, so the job cannot replace it
+    if !exec.dependencies.is_empty() {
+        exec.dependencies.mirror("pip")?;
+
+        let requirements: String = exec
+            .dependencies
+            .packages
+            .iter()
+            .map(
+                |(name, version)| match version.starts_with(|c: char| c.is_ascii_digit()) {
+                    true => format!("{}=={}\n", name, version),
+                    false => format!("{}{}\n", name, version),
+                },
+            )
+            .collect();
+
+        if let Err(err) = std::fs::write(format!("{}/requirements.txt", file_dir), requirements) {
+            return Err(RuntimeError::WriteFailed(err.to_string()));
+        }
     }
 
+    Ok(())
+}
+
+fn compile(exec: &Executor) -> Vec<BuildStep> {
+    // Packages are installed next to the job, from the mirror's wheels
+    let (false, Ok(mirror)) = (
+        exec.dependencies.is_empty(),
+        exec.dependencies.mirror("pip"),
+    ) else {
+        return vec![];
+    };
+
+    let mut installer = Command::new("python3");
+    installer.current_dir(exec.workspace()).args([
+        "-m",
+        "pip",
+        "install",
+        "--disable-pip-version-check",
+        "--no-index",
+        "--find-links",
+        &mirror,
+        "--target",
+        PACKAGES,
+        "-r",
+        "requirements.txt",
+    ]);
+
+    vec![BuildStep::new(installer, DiagnosticFormat::None)]
+}
+
+fn check(exec: &Executor) -> Vec<BuildStep> {
+    let mut checker = Command::new("python3");
+    checker
+        .current_dir(exec.workspace())
+        .args(["-c", PY_COMPILE])
+        .args(sources_with(exec, &["py"]));
+
+    vec![BuildStep::new(checker, DiagnosticFormat::Positional)]
+}
+
+fn execute(exec: &Executor) -> Command {
     let mut new_args = exec.commandline_arguments.arguments.clone();
-    new_args.insert(0, format!("{}/{}.py", file_dir, exec.id));
-
-    let now = Instant::now();
-
-    // Execute File
-    let execution = Command::new("python3")
-        .args(new_args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .unwrap();
-
-    Ok(ChildWrapper {
-        child: execution,
-        start_time: now,
-    })
+    new_args.insert(0, exec.entrypoint.clone());
+
+    // Execute File, unbuffered so that each print() is flushed
+    let mut command = Command::new("python3");
+    command
+        .current_dir(&exec.allocated_dir)
+        .env("PYTHONPATH", PACKAGES)
+        .arg("-u")
+        .args(new_args);
+
+    command
 }
@@ -1,63 +1,130 @@
 use crate::exec::Executor;
-use crate::lang::RuntimeError;
+use crate::lang::{
+    require_entrypoint, write_sources, BuildStep, DiagnosticFormat, LanguageExecutor, RuntimeError,
+};
 use std::process::Command as LinearCommand;
-use std::{process::Stdio, time::Instant};
-use tokio::{process::Command, sync::MutexGuard};
 
-use super::ChildWrapper;
+pub const EXECUTOR: LanguageExecutor = LanguageExecutor {
+    prepare,
+    compile,
+    check,
+    execute,
+    toolchain: &["rustc", "--version"],
+    artifacts: &["target/release/job"],
+    template: Some("fn main() {}\n"),
+    reserved: &["target", ".cargo", "Cargo.toml", "Cargo.lock", "build.rs"],
+    entrypoint: "src/main.rs",
+};
 
-pub fn run(exec: &MutexGuard<Executor>) -> Result<ChildWrapper, RuntimeError> {
-    // Create File and Fill
-    let file_dir = exec.allocated_dir.to_string();
-    let file_contents: String = exec.src_file.clone();
+/// Package section of the manifest, the binary is declared per job
+const MANIFEST: &str = "[package]
+name = \"job\"
+version = \"0.1.0\"
+edition = \"2021\"
+autobins = false
+build = false
+";
 
-    // Execute File
-    let mut init = match LinearCommand::new("cargo")
-        .args(["init", "--name", "job"])
-        .current_dir(&file_dir)
-        .spawn()
-    {
-        Ok(child) => child,
-        Err(err) => return Err(RuntimeError::InitializationFailure(err.to_string())),
-    };
-
-    match init.wait() {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::InitializationFailure(err.to_string())),
+/// Entries of a workspace which outlive the job that wrote them
+const RETAINED: [&str; 4] = ["target", ".cargo", "Cargo.toml", "Cargo.lock"];
+
+fn prepare(exec: &Executor) -> Result<(), RuntimeError> {
+    require_entrypoint(exec)?;
+    let file_dir = exec.workspace().to_string();
+
+    // A warm workspace still holds the previous job's sources
+    if let Ok(entries) = std::fs::read_dir(&file_dir) {
+        for entry in entries.flatten() {
+            if RETAINED
+                .iter()
+                .any(|retained| entry.file_name() == *retained)
+            {
+                continue;
+            }
+
+            let path = entry.path();
+            let removed = match path.is_dir() {
+                true => std::fs::remove_dir_all(&path),
+                false => std::fs::remove_file(&path),
+            };
+
+            if let Err(err) = removed {
+                return Err(RuntimeError::WriteFailed(err.to_string()));
+            }
+        }
     }
 
-    match std::fs::write(format!("{}/src/main.rs", &file_dir), file_contents) {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::WriteFailed(err.to_string())),
+    write_sources(exec)?;
+
+    // Written after the sources, so the job cannot replace it
+    let mut manifest = format!(
+        "{}\n[[bin]]\nname = \"job\"\npath = {}\n\n[dependencies]\n",
+        MANIFEST,
+        serde_json::to_string(&exec.entrypoint).unwrap()
+    );
+    for (name, version) in &exec.dependencies.packages {
+        manifest.push_str(&format!("{} = \"{}\"\n", name, version));
     }
+    write_if_changed(format!("{}/Cargo.toml", &file_dir), &manifest)?;
 
-    // Execute File
-    let mut execution = LinearCommand::new("cargo")
-        .args(["build", "--release"])
-        .current_dir(&file_dir)
-        .spawn()
-        .unwrap();
-
-    match execution.wait() {
-        Ok(_) => {}
-        Err(err) => return Err(RuntimeError::InitializationFailure(err.to_string())),
+    // Crates are only ever taken from the vendored mirror
+    let config_path = format!("{}/.cargo/config.toml", &file_dir);
+    if exec.dependencies.mirror.is_some() || !exec.dependencies.is_empty() {
+        let config = format!(
+            "[source.crates-io]\nreplace-with = \"mirror\"\n\n[source.mirror]\ndirectory = \"{}\"\n",
+            exec.dependencies.mirror("cargo")?
+        );
+
+        if let Err(err) = std::fs::create_dir_all(format!("{}/.cargo", &file_dir)) {
+            return Err(RuntimeError::WriteFailed(err.to_string()));
+        }
+        write_if_changed(config_path, &config)?;
+    } else if let Err(err) = std::fs::remove_file(&config_path) {
+        // Whatever configuration a previous job left behind is not kept
+        if err.kind() != std::io::ErrorKind::NotFound {
+            return Err(RuntimeError::WriteFailed(err.to_string()));
+        }
     }
 
-    let args = exec.commandline_arguments.arguments.clone();
-    let now = Instant::now();
+    Ok(())
+}
+
+/// Left untouched when unchanged, a workspace's build then stays fresh
+fn write_if_changed(path: String, contents: &str) -> Result<(), RuntimeError> {
+    if std::fs::read_to_string(&path).ok().as_deref() == Some(contents) {
+        return Ok(());
+    }
 
+    match std::fs::write(&path, contents) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(RuntimeError::WriteFailed(err.to_string())),
+    }
+}
+
+fn compile(exec: &Executor) -> Vec<BuildStep> {
+    let mut compiler = LinearCommand::new("cargo");
+    compiler
+        .args(["build", "--release", "--offline", "--message-format=json"])
+        .current_dir(exec.workspace());
+
+    vec![BuildStep::new(compiler, DiagnosticFormat::Cargo)]
+}
+
+fn check(exec: &Executor) -> Vec<BuildStep> {
+    let mut checker = LinearCommand::new("cargo");
+    checker
+        .args(["check", "--offline", "--message-format=json"])
+        .current_dir(exec.workspace());
+
+    vec![BuildStep::new(checker, DiagnosticFormat::Cargo)]
+}
+
+fn execute(exec: &Executor) -> LinearCommand {
     // Execute File
-    let execution = Command::new("./target/release/job")
-        .current_dir(file_dir)
-        .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .unwrap();
-
-    Ok(ChildWrapper {
-        child: execution,
-        start_time: now,
-    })
+    let mut command = LinearCommand::new("./target/release/job");
+    command
+        .current_dir(&exec.allocated_dir)
+        .args(exec.commandline_arguments.arguments.clone());
+
+    command
 }
@@ -0,0 +1,125 @@
+use crate::exec::Executor;
+use crate::lang::RuntimeError;
+use std::fs;
+use std::path::{Component, Path};
+
+/// Writes each of the job's files into its workspace, see [`check_path`]
+pub fn write_sources(exec: &Executor) -> Result<(), RuntimeError> {
+    let root = Path::new(exec.workspace());
+    let reserved = exec
+        .language
+        .executor()
+        .map_or(&[][..], |executor| executor.reserved);
+
+    for (path, contents) in &exec.files {
+        check_path(path, reserved)?;
+
+        let destination = root.join(path);
+        if let Some(parent) = destination.parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                return Err(RuntimeError::WriteFailed(err.to_string()));
+            }
+        }
+
+        if let Err(err) = fs::write(destination, contents) {
+            return Err(RuntimeError::WriteFailed(err.to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Paths must stay within the workspace, so absolute paths and `..` components
+/// are refused, as are those beneath one of its `reserved` entries.
+pub fn check_path(path: &str, reserved: &[&str]) -> Result<(), RuntimeError> {
+    let relative = Path::new(path);
+    let contained = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+    if path.is_empty() || !contained {
+        return Err(RuntimeError::InvalidFile(format!(
+            "'{}' is not a relative path within the job",
+            path
+        )));
+    }
+
+    let top = relative.components().find_map(|component| match component {
+        Component::Normal(name) => Some(name),
+        _ => None,
+    });
+
+    match top {
+        Some(name) if reserved.iter().any(|entry| name == *entry) => {
+            Err(RuntimeError::InvalidFile(format!(
+                "'{}' is reserved for the build, and cannot be written",
+                path
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Refuses jobs whose entrypoint is not amongst their files
+pub fn require_entrypoint(exec: &Executor) -> Result<(), RuntimeError> {
+    match exec.files.contains_key(&exec.entrypoint) {
+        true => Ok(()),
+        false => Err(RuntimeError::InvalidFile(format!(
+            "Entrypoint '{}' is not amongst the job's files",
+            exec.entrypoint
+        ))),
+    }
+}
+
+/// Paths of the job's files with one of `extensions`, in a stable order
+pub fn sources_with(exec: &Executor, extensions: &[&str]) -> Vec<String> {
+    exec.files
+        .keys()
+        .filter(|path| {
+            Path::new(path)
+                .extension()
+                .is_some_and(|extension| extensions.iter().any(|wanted| extension == *wanted))
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_path;
+
+    const RESERVED: &[&str] = &["target", ".cargo", "Cargo.toml"];
+
+    #[test]
+    fn accepts_paths_within_the_job() {
+        for path in [
+            "main.rs",
+            "src/main.rs",
+            "./src/lib.rs",
+            "src/target/mod.rs",
+        ] {
+            assert!(check_path(path, RESERVED).is_ok(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn refuses_paths_leaving_the_job() {
+        for path in ["", "/etc/passwd", "../main.rs", "src/../../main.rs"] {
+            assert!(check_path(path, RESERVED).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn refuses_reserved_entries() {
+        for path in [
+            "Cargo.toml",
+            "./Cargo.toml",
+            ".cargo/config.toml",
+            "target/release/job",
+        ] {
+            assert!(check_path(path, RESERVED).is_err(), "{}", path);
+        }
+
+        assert!(check_path("Cargo.toml", &[]).is_ok());
+    }
+}
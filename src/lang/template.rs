@@ -0,0 +1,224 @@
+use crate::config::Settings;
+use crate::exec::{Environment, ExecutorBuilder, Limits};
+use crate::lang::{signal_group, spawn, BuildStep, Languages};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout_at;
+use uuid::Uuid;
+
+struct Pool {
+    free: Mutex<Vec<String>>,
+    available: Arc<Semaphore>,
+}
+
+/// Workspaces kept warm for languages with a template. Each is a fixed
+/// directory whose build output survives between jobs, so only what a job
+/// changed is rebuilt. A workspace is only ever held by one job at a time.
+pub struct Templates {
+    pools: HashMap<Languages, Arc<Pool>>,
+}
+
+/// A workspace held by a job, returned to its pool once dropped
+pub struct Workspace {
+    pub path: String,
+    pool: Arc<Pool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        self.pool.free.lock().unwrap().push(self.path.clone());
+    }
+}
+
+impl Templates {
+    pub fn open(settings: &Settings) -> Self {
+        let mut pools = HashMap::new();
+
+        for language in Languages::ALL {
+            let Some(executor) = language.executor() else {
+                continue;
+            };
+
+            if executor.template.is_none() || settings.template_slots == 0 {
+                continue;
+            }
+
+            let slots: Vec<String> = (0..settings.template_slots)
+                .map(|slot| {
+                    format!(
+                        "{}/{}/{}",
+                        settings.template_directory,
+                        language.as_string(),
+                        slot
+                    )
+                })
+                .collect();
+
+            for slot in &slots {
+                if let Err(err) = std::fs::create_dir_all(slot) {
+                    println!("[TEMPLATE]: Failed to create workspace {}, {}", slot, err);
+                }
+            }
+
+            pools.insert(
+                language,
+                Arc::new(Pool {
+                    available: Arc::new(Semaphore::new(slots.len())),
+                    free: Mutex::new(slots),
+                }),
+            );
+        }
+
+        Templates { pools }
+    }
+
+    /// Waits for a free workspace, `None` if the language has no template
+    pub async fn acquire(&self, language: Languages) -> Option<Workspace> {
+        let pool = self.pools.get(&language)?.clone();
+        let permit = pool.available.clone().acquire_owned().await.ok()?;
+        let path = pool.free.lock().unwrap().pop()?;
+
+        Some(Workspace {
+            path,
+            pool,
+            _permit: permit,
+        })
+    }
+
+    /// Builds and checks each language's seed program in all of its
+    /// workspaces, so that the first jobs already find them warm.
+    pub async fn warm(&self, settings: &Settings) {
+        for (language, pool) in &self.pools {
+            let Some(seed) = language.executor().and_then(|executor| executor.template) else {
+                continue;
+            };
+
+            // Held until every workspace is warm, so none is handed out twice
+            let mut held = vec![];
+            let slots = pool.free.lock().unwrap().len();
+            for _ in 0..slots {
+                let Some(workspace) = self.acquire(*language).await else {
+                    break;
+                };
+
+                let mut exec = ExecutorBuilder::new()
+                    .language(*language)
+                    .src_file(Some(seed.to_string()))
+                    .environment(Environment {
+                        base: settings.environment(*language),
+                        program: BTreeMap::new(),
+                    })
+                    .limits(Limits {
+                        time_limit: settings.compile_time_limit,
+                        compile_time_limit: settings.compile_time_limit,
+                        idle_timeout: None,
+                        kill_grace_period: settings.kill_grace_period,
+                        archive_size: settings.archive_size,
+                        archive_files: settings.archive_files,
+                        artifact_size: settings.artifact_size,
+                        artifact_files: settings.artifact_files,
+                        run_resources: settings.compile_resources,
+                        compile_resources: settings.compile_resources,
+                        output: settings.output_limits,
+                    })
+                    .build(Uuid::nil());
+                exec.workspace = Some(workspace.path.clone());
+
+                let executor = language.executor().expect("Template without an executor");
+                if let Err(err) = (executor.prepare)(&exec) {
+                    println!("[TEMPLATE]: Failed to seed {}, {:?}", workspace.path, err);
+                    held.push(workspace);
+                    continue;
+                }
+
+                let steps = (executor.compile)(&exec)
+                    .into_iter()
+                    .chain((executor.check)(&exec));
+
+                // The steps share the time limit, as those of a job's build do
+                let deadline = Instant::now() + settings.compile_time_limit;
+                let mut warmed = true;
+
+                for BuildStep { mut command, .. } in steps {
+                    command
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null());
+                    exec.environment.base(&mut command);
+
+                    let status = match spawn(command, settings.compile_resources, None) {
+                        Ok(mut child) => match timeout_at(deadline.into(), child.wait()).await {
+                            Ok(status) => status.map_err(|err| err.to_string()),
+                            Err(_) => {
+                                signal_group(child.id(), libc::SIGKILL);
+                                let _ = child.wait().await;
+                                Err("Compile time limit exceeded".to_string())
+                            }
+                        },
+                        Err(err) => Err(err.to_string()),
+                    };
+
+                    match status {
+                        Ok(status) if status.success() => {}
+                        status => {
+                            println!(
+                                "[TEMPLATE]: Failed to warm {}, {:?}",
+                                workspace.path, status
+                            );
+                            warmed = false;
+                            break;
+                        }
+                    }
+                }
+
+                if warmed {
+                    println!("[TEMPLATE]: Warmed {}", workspace.path);
+                }
+                held.push(workspace);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Templates;
+    use crate::config::Settings;
+    use std::path::Path;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn settings(compile_time_limit: Duration) -> Settings {
+        let directory = std::env::temp_dir().join(format!("roadrunner-{}", Uuid::new_v4()));
+
+        Settings {
+            template_directory: directory.to_string_lossy().to_string(),
+            template_slots: 1,
+            compile_time_limit,
+            ..Settings::from_env()
+        }
+    }
+
+    async fn warmed(settings: &Settings) -> bool {
+        Templates::open(settings).warm(settings).await;
+
+        let built = Path::new(&settings.template_directory).join("rust/0/target/release/job");
+        let exists = built.exists();
+        let _ = std::fs::remove_dir_all(&settings.template_directory);
+        exists
+    }
+
+    #[tokio::test]
+    async fn builds_each_workspace() {
+        assert!(warmed(&settings(Duration::from_secs(120))).await);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_compile_time_limit() {
+        assert!(!warmed(&settings(Duration::ZERO)).await);
+    }
+}
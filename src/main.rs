@@ -1,7 +1,11 @@
+mod cache;
+mod cgroup;
+mod config;
 mod exec;
 mod lang;
 mod pool;
 mod runner;
+mod sandbox;
 mod ws;
 
 pub use chrono;
@@ -13,8 +17,18 @@ use std::{convert::Infallible, sync::Arc};
 use tokio::sync::Mutex;
 use warp::Filter;
 
+fn main() {
+    // Jobs' programs are started through the server's own binary, see `sandbox::supervise`
+    if let Some(args) = sandbox::supervisor_args() {
+        sandbox::supervise(args);
+    }
+
+    serve();
+}
+
 #[tokio::main]
-async fn main() {
+async fn serve() {
+    dotenv::dotenv().ok();
     let config: Locked<GlobalState> = Arc::new(Mutex::new(GlobalState::initialize()));
 
     let ws_route = warp::path::path("ws")
@@ -24,13 +38,18 @@ async fn main() {
 
     let echo_route = warp::path::end().and(warp::get()).and_then(ws::echo);
 
+    let (templates, settings) = {
+        let config_lock = config.lock().await;
+        (config_lock.templates.clone(), config_lock.settings.clone())
+    };
+    tokio::spawn(async move { templates.warm(&settings).await });
+
     tokio::spawn(async move { Pool::new().begin(config).await });
 
     let routes = ws_route
         .or(echo_route)
         .with(warp::cors().allow_any_origin());
 
-    dotenv::dotenv().ok();
     // let _certificate = dotenv::var("CERTIFICATE").unwrap();
     // let _private_key = dotenv::var("PRIVATE_KEY").unwrap();
     let port: u16 = from_str::<u16>(&dotenv::var("PORT").unwrap()).unwrap();
@@ -1,94 +1,252 @@
-use crate::exec::{Executor, TerminalFeed, TerminalStream, TerminalStreamType};
-use crate::lang::Languages;
+use crate::cache::BuildCache;
+use crate::cgroup::Cgroups;
+use crate::config::Settings;
+use crate::exec::{Delivery, Executor, TerminalFeed, TerminalStream, TerminalStreamType};
+use crate::lang::{artifact_frame, ExecutionOutput, Languages, RuntimeError, Templates};
 use crate::runner::{GlobalState, Locked};
 
-use futures_timer::Delay;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Notify;
 use warp::ws::Message;
 
-#[derive(Copy, Clone)]
-pub struct Pool {}
+/// Jobs currently executing, in total and by language
+#[derive(Default)]
+struct Occupancy {
+    running: usize,
+    languages: HashMap<Languages, usize>,
+}
+
+/// A job's place amongst those executing, freed however the job ends
+struct Slot {
+    pool: Pool,
+    language: Languages,
+    notifier: Arc<Notify>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.pool.release(self.language);
+
+        // Let the pool look for queued work which fits
+        self.notifier.notify_one();
+    }
+}
+
+#[derive(Clone)]
+pub struct Pool {
+    /// Only ever held briefly and never across an await, so slots free without a runtime
+    occupancy: Arc<Mutex<Occupancy>>,
+}
 
 impl Pool {
     pub fn new() -> Self {
-        Pool {}
+        Pool {
+            occupancy: Arc::new(Mutex::new(Occupancy::default())),
+        }
     }
 
     pub async fn begin(self, config: Locked<GlobalState>) {
-        tokio::spawn(async move {
-            loop {
-                let conf_clone = config.clone();
+        let notifier = config.lock().await.task_notifier.clone();
+
+        loop {
+            // Woken by submissions and completions, a permit is held if we aren't waiting yet
+            notifier.notified().await;
+
+            let config_lock = config.lock().await;
+            let settings = config_lock.settings.clone();
+            let mut dispatched = false;
 
-                tokio::spawn(async move {
-                    let config_lock = conf_clone.lock().await;
-                    let mut task_queue = config_lock.task_queue.lock().await;
+            while let Some(task) = self.next(&config_lock, &settings).await {
+                dispatched = true;
 
-                    if let Some(task) = task_queue.pop_front() {
-                        // Have some task to perform.
-                        println!("[POOL]: Found task to perform");
-                        drop(task_queue);
+                // Have some task to perform.
+                println!("[POOL]: Found task to perform");
 
-                        let sid = task.lock().await.sender_id;
+                let (sid, id, language) = {
+                    let unlocked = task.lock().await;
+                    (unlocked.sender_id, unlocked.id, unlocked.language)
+                };
 
-                        let sender = config_lock
-                            .clients
+                let sender = match config_lock.clients.lock().await.get(&sid.to_string()) {
+                    Some(client) => client.sender.clone(),
+                    None => {
+                        println!("[POOL]: Sender left before task started, dropping.");
+                        self.release(language);
+                        config_lock
+                            .runners
                             .lock()
                             .await
-                            .get(&sid.to_string())
-                            .unwrap()
-                            .sender
-                            .clone();
-
-                        println!("[POOL]: Got sender, starting!");
-                        let task_copy = task.clone();
-
-                        config_lock.runtime.lock().await.spawn(async move {
-                            let value = self.execute(task_copy, sender).await;
-                            println!("[POOL]: Ended with output, {:?}", value);
-                            task.lock().await.terminal_feed = value;
-                        });
-                    } else {
-                        // Sleep Queue
-                        Delay::new(Duration::from_millis(1000)).await;
+                            .retain(|_, runner| runner.executor != Some(id));
+                        continue;
                     }
-                })
-                .await
-                .unwrap()
+                };
+
+                println!("[POOL]: Got sender, starting!");
+                let task_copy = task.clone();
+                let runners = config_lock.runners.clone();
+                let slot = Slot {
+                    pool: self.clone(),
+                    language,
+                    notifier: notifier.clone(),
+                };
+                let pool = self.clone();
+                let cache = config_lock.cache.clone();
+                let templates = config_lock.templates.clone();
+                let cgroups = config_lock.cgroups.clone();
+
+                config_lock.runtime.lock().await.spawn(async move {
+                    let value = pool
+                        .execute(task_copy, sender, cache, templates, cgroups)
+                        .await;
+                    for end in &value.output {
+                        match &end.value {
+                            Some(output) => {
+                                println!("[POOL]: Ended {:?}, {}", end.nonce, summary(output))
+                            }
+                            None => println!("[POOL]: Ended {:?} with an error", end.nonce),
+                        }
+                    }
+
+                    let mut unlocked = task.lock().await;
+                    unlocked.terminal_feed = value;
+
+                    // Job is no longer addressable by its nonce
+                    runners
+                        .lock()
+                        .await
+                        .retain(|_, runner| runner.executor != Some(unlocked.id));
+
+                    drop(slot);
+                });
             }
-        })
-        .await
-        .unwrap()
+
+            if dispatched {
+                Pool::announce_positions(&config_lock).await;
+            }
+        }
+    }
+
+    /// Tells every queued job where it now stands in the queue
+    pub async fn announce_positions(config: &GlobalState) {
+        let task_queue = config.task_queue.lock().await;
+        let clients = config.clients.lock().await;
+
+        for (index, task) in task_queue.iter().enumerate() {
+            let unlocked = task.lock().await;
+            let Some(client) = clients.get(&unlocked.sender_id.to_string()) else {
+                continue;
+            };
+
+            let event = TerminalStream::new_event(
+                TerminalStreamType::Queued {
+                    position: index + 1,
+                },
+                unlocked.nonce.clone(),
+            );
+
+            let _ = client
+                .sender
+                .send(Message::text(serde_json::to_string(&event).unwrap()));
+        }
+    }
+
+    /// Takes the first queued task which fits within the concurrency limits,
+    /// occupying its slot. Tasks for a saturated language are passed over.
+    async fn next(&self, config: &GlobalState, settings: &Settings) -> Option<Locked<Executor>> {
+        let mut task_queue = config.task_queue.lock().await;
+
+        let mut languages = Vec::with_capacity(task_queue.len());
+        for task in task_queue.iter() {
+            languages.push(task.lock().await.language);
+        }
+
+        let index = self.occupy(
+            &languages,
+            settings.max_concurrent_jobs,
+            &settings.language_concurrency,
+        )?;
+
+        task_queue.remove(index)
+    }
+
+    /// Occupies a slot for the first of `languages` within the limits, giving its index
+    fn occupy(
+        &self,
+        languages: &[Languages],
+        max_concurrent_jobs: usize,
+        language_concurrency: &HashMap<Languages, usize>,
+    ) -> Option<usize> {
+        let mut occupancy = self
+            .occupancy
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if occupancy.running >= max_concurrent_jobs {
+            return None;
+        }
+
+        let index = languages.iter().position(|language| {
+            let running = occupancy.languages.get(language).copied().unwrap_or(0);
+            !matches!(language_concurrency.get(language), Some(limit) if running >= *limit)
+        })?;
+
+        occupancy.running += 1;
+        *occupancy.languages.entry(languages[index]).or_insert(0) += 1;
+
+        Some(index)
+    }
+
+    fn release(&self, language: Languages) {
+        let mut occupancy = self
+            .occupancy
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        occupancy.running -= 1;
+        if let Some(running) = occupancy.languages.get_mut(&language) {
+            *running -= 1;
+        }
     }
 
     pub async fn execute(
         &self,
         locked_task: Locked<Executor>,
         sender: UnboundedSender<Message>,
+        cache: Arc<BuildCache>,
+        templates: Arc<Templates>,
+        cgroups: Arc<Cgroups>,
     ) -> TerminalFeed {
         let mut tx2 = locked_task.lock().await.broadcast.0.clone().subscribe();
         println!("[EXEC]: Performing task from sender");
 
-        let file_dir = locked_task.lock().await.allocated_dir.clone();
+        let (file_dir, delivery) = {
+            let unlocked = locked_task.lock().await;
+            (unlocked.allocated_dir.clone(), unlocked.artifacts.delivery)
+        };
         // Template create all the directories necessary
         match std::fs::create_dir_all(&file_dir) {
             Ok(_) => {}
             Err(err) => println!("[POOL]: Failed to create directory, {}", err),
         }
 
-        tokio::spawn(async move {
+        let broadcast = locked_task.lock().await.broadcast.0.clone();
+        let nonce = locked_task.lock().await.nonce.clone();
+
+        let run = tokio::spawn(async move {
             let unlocked = locked_task.lock().await;
             let broadcast_stream = unlocked.broadcast.0.clone();
             let nonce = unlocked.nonce.clone();
             let name = unlocked.id;
 
-            match Languages::run(unlocked).await {
+            match Languages::run(unlocked, &cache, &templates, &cgroups).await {
                 Ok(val) => {
                     println!("[PROG:{}]: Completed Execution.", name);
                     broadcast_stream
                         .send(TerminalStream::new_output(
                             TerminalStreamType::EndOfOutput,
-                            val,
+                            val.clone(),
                             nonce,
                         ))
                         .unwrap();
@@ -108,6 +266,18 @@ impl Pool {
             }
         });
 
+        // A job which panicked never sent its end, so the feed would wait forever
+        tokio::spawn(async move {
+            if let Err(err) = run.await {
+                println!("[PROG]: Execution panicked, {}", err);
+                let _ = broadcast.send(TerminalStream::new(
+                    TerminalStreamType::EndOfOutput,
+                    RuntimeError::Panicked(err.to_string()).as_string(),
+                    nonce,
+                ));
+            }
+        });
+
         let feed = tokio::spawn(async move {
             // We can listen to the stream of inputs/outputs
             let mut feed = TerminalFeed {
@@ -119,12 +289,34 @@ impl Pool {
 
             loop {
                 match tx2.recv().await {
-                    Ok(terminal_stream) => {
+                    Ok(mut terminal_stream) => {
+                        // Artifacts sent as frames precede the output describing them
+                        if let (Delivery::Frames, Some(output)) =
+                            (delivery, terminal_stream.value.as_mut())
+                        {
+                            for artifact in &mut output.artifacts {
+                                if let Some(contents) = artifact.contents.take() {
+                                    let frame = artifact_frame(
+                                        terminal_stream.nonce.as_deref(),
+                                        artifact,
+                                        &contents,
+                                    );
+                                    sender.send(Message::binary(frame)).unwrap();
+                                }
+                            }
+                        }
+
                         // Send to websocket listener
                         let as_string = serde_json::to_string(&terminal_stream).unwrap();
-                        println!("[STREAM]: Sending value, '{}' to ws", as_string);
                         sender.send(Message::text(as_string)).unwrap();
 
+                        // Once sent, the stored feed need not hold the artifacts too
+                        if let Some(output) = terminal_stream.value.as_mut() {
+                            for artifact in &mut output.artifacts {
+                                artifact.contents = None;
+                            }
+                        }
+
                         // Push into logs
                         match terminal_stream.terminal_type {
                             TerminalStreamType::StandardOutput => {
@@ -140,12 +332,19 @@ impl Pool {
                                 feed.output.push(terminal_stream);
                                 break;
                             }
+                            // Lifecycle events are only forwarded
+                            TerminalStreamType::Queued { .. }
+                            | TerminalStreamType::Compiling
+                            | TerminalStreamType::CompilerOutput
+                            | TerminalStreamType::Running
+                            | TerminalStreamType::Finished { .. }
+                            | TerminalStreamType::Truncated { .. } => {}
                         };
                     }
-                    Err(_) => {
-                        println!("[TERM]: Poor receive.");
-                        // No new input
+                    Err(RecvError::Lagged(skipped)) => {
+                        println!("[TERM]: Poor receive, skipped {} values.", skipped);
                     }
+                    Err(RecvError::Closed) => break,
                 };
             }
 
@@ -161,3 +360,91 @@ impl Pool {
         feed
     }
 }
+
+/// A job's outcome for the log, which leaves out its output and artifacts' contents
+fn summary(output: &ExecutionOutput) -> String {
+    let sizes: Vec<u64> = output
+        .artifacts
+        .iter()
+        .map(|artifact| artifact.size)
+        .collect();
+    format!(
+        "{:?}, {} artifacts of {:?} bytes",
+        output.reason,
+        sizes.len(),
+        sizes
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pool, Slot};
+    use crate::lang::Languages;
+
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Notify;
+
+    fn slot(pool: &Pool, notifier: &Arc<Notify>) -> Slot {
+        Slot {
+            pool: pool.clone(),
+            language: Languages::Python,
+            notifier: notifier.clone(),
+        }
+    }
+
+    #[test]
+    fn occupies_within_the_limits() {
+        let pool = Pool::new();
+        let limits = HashMap::from([(Languages::Python, 1)]);
+        let queue = [Languages::Python, Languages::Python, Languages::Rust];
+
+        assert_eq!(pool.occupy(&queue, 3, &limits), Some(0));
+        // Python is saturated, so the Rust job is taken ahead of it
+        assert_eq!(pool.occupy(&queue, 3, &limits), Some(2));
+        assert_eq!(pool.occupy(&queue[..2], 3, &limits), None);
+
+        pool.release(Languages::Python);
+        assert_eq!(pool.occupy(&queue, 2, &limits), Some(0));
+        assert_eq!(pool.occupy(&queue[2..], 2, &limits), None);
+    }
+
+    #[tokio::test]
+    async fn a_panicking_job_frees_its_slot() {
+        let pool = Pool::new();
+        let notifier = Arc::new(Notify::new());
+        let limits = HashMap::new();
+
+        assert_eq!(pool.occupy(&[Languages::Python], 1, &limits), Some(0));
+        assert_eq!(pool.occupy(&[Languages::Python], 1, &limits), None);
+
+        let slot = slot(&pool, &notifier);
+        let job = tokio::spawn(async move {
+            let _slot = slot;
+            panic!("job failed");
+        });
+        assert!(job.await.unwrap_err().is_panic());
+
+        assert_eq!(pool.occupy(&[Languages::Python], 1, &limits), Some(0));
+        tokio::time::timeout(Duration::from_secs(1), notifier.notified())
+            .await
+            .expect("pool was not woken");
+    }
+
+    #[test]
+    fn frees_its_slot_without_a_runtime() {
+        let pool = Pool::new();
+        let notifier = Arc::new(Notify::new());
+
+        assert_eq!(
+            pool.occupy(&[Languages::Python], 1, &HashMap::new()),
+            Some(0)
+        );
+        drop(slot(&pool, &notifier));
+        assert_eq!(
+            pool.occupy(&[Languages::Python], 1, &HashMap::new()),
+            Some(0)
+        );
+    }
+}
@@ -1,11 +1,29 @@
+use crate::cache::BuildCache;
+use crate::cgroup::Cgroups;
+use crate::config::Settings;
+use crate::exec::Arguments;
+use crate::exec::Artifacts;
+use crate::exec::CommandlineArguments;
+use crate::exec::Delivery;
+use crate::exec::Dependencies;
+use crate::exec::Environment;
 use crate::exec::Executor;
 use crate::exec::ExecutorBuilder;
-use crate::lang::Languages;
+use crate::exec::ExecutorControl;
+use crate::exec::Framing;
+use crate::exec::Limits;
+use crate::exec::Mode;
+use crate::exec::OutputLimits;
+use crate::exec::OutputPolicy;
+use crate::exec::ResourceLimits;
+use crate::exec::WindowSize;
+use crate::lang::{Languages, Templates};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use uuid::Uuid;
 use warp::ws::Message;
 
@@ -16,6 +34,7 @@ pub type Locked<T> = Arc<Mutex<T>>;
 #[derive(Clone)]
 pub struct Client {
     pub id: Uuid,
+    #[allow(dead_code)]
     pub job_history: Vec<Runner>,
     pub sender: UnboundedSender<Message>,
 }
@@ -32,18 +51,30 @@ impl Client {
 
 pub struct GlobalState {
     pub task_queue: Locked<VecDeque<Locked<Executor>>>,
+    pub task_notifier: Arc<Notify>,
     pub runners: Locked<HashMap<String, Runner>>,
     pub clients: Locked<HashMap<String, Client>>,
     pub runtime: Locked<Runtime>,
+    pub settings: Arc<Settings>,
+    pub cache: Arc<BuildCache>,
+    pub templates: Arc<Templates>,
+    pub cgroups: Arc<Cgroups>,
 }
 
 impl GlobalState {
     pub fn initialize() -> Self {
+        let settings = Settings::from_env();
+
         GlobalState {
             task_queue: Arc::new(Mutex::new(VecDeque::new())),
+            task_notifier: Arc::new(Notify::new()),
             runners: Arc::new(Mutex::new(HashMap::new())),
             clients: Arc::new(Mutex::new(HashMap::new())),
             runtime: Arc::new(Mutex::new(Runtime::new().unwrap())),
+            cache: Arc::new(BuildCache::open(&settings)),
+            templates: Arc::new(Templates::open(&settings)),
+            cgroups: Arc::new(Cgroups::open(&settings)),
+            settings: Arc::new(settings),
         }
     }
 }
@@ -53,37 +84,184 @@ pub struct Runner {
     pub id: Uuid,
     pub nonce: String,
 
-    pub source: String,
+    pub source: Option<String>,
+    pub files: BTreeMap<String, String>,
+    pub archive: Option<Vec<u8>>,
+    pub entrypoint: Option<String>,
     pub language: Languages,
+    pub mode: Mode,
+    pub dependencies: BTreeMap<String, String>,
+    pub artifacts: Vec<String>,
+    pub artifact_delivery: Delivery,
 
-    pub commandline_arguments: String,
+    pub commandline_arguments: Arguments,
     pub standard_input: String,
+    pub interactive: bool,
+    pub output_framing: Framing,
+    pub tty: Option<WindowSize>,
+    pub env: BTreeMap<String, String>,
+
+    pub time_limit_ms: Option<u64>,
+    pub idle_timeout_ms: Option<u64>,
+    pub resource_limits: Option<ResourceLimits>,
+    pub output_policy: Option<OutputPolicy>,
 
     pub requestee: Uuid,
     pub executor: Option<Uuid>, // Id
+    pub control: Option<UnboundedSender<ExecutorControl>>,
 }
 
 impl Runner {
-    pub fn batch(self) -> Executor {
+    pub fn batch(self, settings: &Settings) -> Executor {
+        let time_limit = self
+            .time_limit_ms
+            .map(Duration::from_millis)
+            .unwrap_or(settings.default_time_limit)
+            .min(settings.max_time_limit);
+
+        let requested = self.resource_limits.unwrap_or_default();
+        let limits = Limits {
+            time_limit,
+            idle_timeout: self
+                .idle_timeout_ms
+                .map(Duration::from_millis)
+                .or(settings.idle_timeout),
+            compile_time_limit: settings.compile_time_limit,
+            kill_grace_period: settings.kill_grace_period,
+            archive_size: settings.archive_size,
+            archive_files: settings.archive_files,
+            artifact_size: settings.artifact_size,
+            artifact_files: settings.artifact_files,
+            run_resources: settings.run_resources(self.language).min(ResourceLimits {
+                // Unless requested, CPU time runs out a second after the time limit
+                cpu_time: requested
+                    .cpu_time
+                    .or(Some(time_limit.as_secs_f64().ceil() as u64 + 1)),
+                ..requested
+            }),
+            compile_resources: settings.compile_resources,
+            output: OutputLimits {
+                policy: self.output_policy.unwrap_or(settings.output_limits.policy),
+                ..settings.output_limits
+            },
+        };
+
+        // A terminal's program is told what it understands, unless asked otherwise
+        let mut env = self.env;
+        if self.tty.is_some() {
+            env.entry("TERM".to_string())
+                .or_insert_with(|| "xterm-256color".to_string());
+        }
+
         ExecutorBuilder::new()
             .language(self.language)
+            .mode(self.mode)
+            .dependencies(Dependencies {
+                packages: self.dependencies,
+                mirror: settings.dependency_mirror.clone(),
+            })
+            .artifacts(Artifacts {
+                patterns: self.artifacts,
+                delivery: self.artifact_delivery,
+            })
+            .sandbox(settings.sandbox.clone())
             .input(self.standard_input)
             .src_file(self.source)
+            .files(self.files)
+            .archive(self.archive)
+            .entrypoint(self.entrypoint)
             .arguments(self.commandline_arguments)
+            .interactive(self.interactive)
+            .framing(self.output_framing)
+            .tty(self.tty)
+            .environment(Environment {
+                base: settings.environment(self.language),
+                program: env,
+            })
+            .limits(limits)
             .nonce(self.nonce)
             .build(self.requestee)
     }
+
+    /// Binds the runner to its executor, so later messages can reach the job.
+    /// The archive now lives with the executor, so is not kept twice.
+    pub fn assign(&mut self, executor: &Executor) {
+        self.archive = None;
+        self.executor = Some(executor.id);
+        self.control = Some(executor.control.0.clone());
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ExecutePacket {
-    pub source: String,
-    pub language: String,
+    pub source: Option<String>,
+    /// Sources by path, written beside `source` if both are given
+    pub files: Option<BTreeMap<String, String>>,
+    /// Base64 tar, tar.gz or zip of the job's files. Binary frames carry it raw instead.
+    pub archive: Option<String>,
+    /// Path the program starts from, defaults to the language's usual file
+    pub entrypoint: Option<String>,
+    /// Name or alias of the language, inferred from `entrypoint` if absent
+    pub language: Option<String>,
+    pub mode: Option<Mode>,
+    /// Package name to version, e.g. `{"rand": "0.8.5"}`
+    pub dependencies: Option<BTreeMap<String, String>>,
+    /// Globs of files returned once the job exits, e.g. `["*.png"]`
+    pub artifacts: Option<Vec<String>>,
+    pub artifact_delivery: Option<Delivery>,
 
     pub nonce: String,
 
-    pub commandline_arguments: Option<String>,
+    /// An array of arguments, or a line of them to be split as a shell would
+    pub commandline_arguments: Option<CommandlineArguments>,
     pub standard_input: Option<String>,
+    pub interactive: Option<bool>,
+    /// Whether output is sent line by line, or in chunks as it arrives
+    pub output_framing: Option<Framing>,
+    /// Runs the program on a pseudo-terminal, of `window` size if given
+    pub tty: Option<bool>,
+    pub window: Option<WindowSize>,
+    /// Variables set for the program alone, e.g. `{"RUST_BACKTRACE": "1"}`
+    pub env: Option<BTreeMap<String, String>>,
+
+    pub time_limit_ms: Option<u64>,
+    pub idle_timeout_ms: Option<u64>,
+    /// Tightens the server's resource limits for this job's program
+    pub resource_limits: Option<ResourceLimits>,
+    /// Whether output beyond the server's caps kills the program or is discarded
+    pub output_policy: Option<OutputPolicy>,
+}
+
+/// Messages addressing a job which has already been submitted
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlPacket {
+    Stdin {
+        nonce: String,
+        data: String,
+    },
+    CloseStdin {
+        nonce: String,
+    },
+    Cancel {
+        nonce: String,
+    },
+    Resize {
+        nonce: String,
+        rows: u16,
+        columns: u16,
+    },
+}
+
+impl ControlPacket {
+    pub fn nonce(&self) -> &str {
+        match self {
+            Self::Stdin { nonce, .. }
+            | Self::CloseStdin { nonce }
+            | Self::Cancel { nonce }
+            | Self::Resize { nonce, .. } => nonce,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -92,10 +270,27 @@ pub struct RunnerBuilder {
     pub nonce: Option<String>,
 
     pub source: Option<String>,
+    pub files: Option<BTreeMap<String, String>>,
+    pub archive: Option<Vec<u8>>,
+    pub entrypoint: Option<String>,
     pub language: Option<Languages>,
+    pub mode: Option<Mode>,
+    pub dependencies: Option<BTreeMap<String, String>>,
+    pub artifacts: Option<Vec<String>>,
+    pub artifact_delivery: Option<Delivery>,
 
-    pub commandline_arguments: Option<String>,
+    pub commandline_arguments: Option<Arguments>,
     pub standard_input: Option<String>,
+    pub interactive: Option<bool>,
+    pub output_framing: Option<Framing>,
+    pub tty: Option<bool>,
+    pub window: Option<WindowSize>,
+    pub env: Option<BTreeMap<String, String>>,
+
+    pub time_limit_ms: Option<u64>,
+    pub idle_timeout_ms: Option<u64>,
+    pub resource_limits: Option<ResourceLimits>,
+    pub output_policy: Option<OutputPolicy>,
 
     pub requestee: Option<Uuid>,
     pub executor: Option<Uuid>, // Id
@@ -107,16 +302,47 @@ impl RunnerBuilder {
             id: Uuid::new_v4(),
             nonce: None,
             source: None,
+            files: None,
+            archive: None,
+            entrypoint: None,
             language: None,
+            mode: None,
+            dependencies: None,
+            artifacts: None,
+            artifact_delivery: None,
             commandline_arguments: None,
             standard_input: None,
+            interactive: None,
+            output_framing: None,
+            tty: None,
+            window: None,
+            env: None,
+            time_limit_ms: None,
+            idle_timeout_ms: None,
+            resource_limits: None,
+            output_policy: None,
             requestee: None,
             executor: None,
         }
     }
 
-    pub fn source(mut self, source: String) -> Self {
-        self.source = Some(source);
+    pub fn source(mut self, source: Option<String>) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn files(mut self, files: Option<BTreeMap<String, String>>) -> Self {
+        self.files = files;
+        self
+    }
+
+    pub fn archive(mut self, archive: Option<Vec<u8>>) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    pub fn entrypoint(mut self, entrypoint: Option<String>) -> Self {
+        self.entrypoint = entrypoint;
         self
     }
 
@@ -125,12 +351,32 @@ impl RunnerBuilder {
         self
     }
 
-    pub fn language(mut self, language: &str) -> Self {
-        self.language = Some(Languages::from_string(language));
+    pub fn language(mut self, language: Languages) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    pub fn mode(mut self, mode: Option<Mode>) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn dependencies(mut self, dependencies: Option<BTreeMap<String, String>>) -> Self {
+        self.dependencies = dependencies;
         self
     }
 
-    pub fn arguments(mut self, commandline_arguments: Option<String>) -> Self {
+    pub fn artifacts(mut self, artifacts: Option<Vec<String>>) -> Self {
+        self.artifacts = artifacts;
+        self
+    }
+
+    pub fn artifact_delivery(mut self, delivery: Option<Delivery>) -> Self {
+        self.artifact_delivery = delivery;
+        self
+    }
+
+    pub fn arguments(mut self, commandline_arguments: Option<Arguments>) -> Self {
         self.commandline_arguments = commandline_arguments;
         self
     }
@@ -140,23 +386,86 @@ impl RunnerBuilder {
         self
     }
 
+    pub fn interactive(mut self, interactive: Option<bool>) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    pub fn output_framing(mut self, output_framing: Option<Framing>) -> Self {
+        self.output_framing = output_framing;
+        self
+    }
+
+    pub fn tty(mut self, tty: Option<bool>) -> Self {
+        self.tty = tty;
+        self
+    }
+
+    pub fn window(mut self, window: Option<WindowSize>) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn env(mut self, env: Option<BTreeMap<String, String>>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn time_limit(mut self, time_limit_ms: Option<u64>) -> Self {
+        self.time_limit_ms = time_limit_ms;
+        self
+    }
+
+    pub fn idle_timeout(mut self, idle_timeout_ms: Option<u64>) -> Self {
+        self.idle_timeout_ms = idle_timeout_ms;
+        self
+    }
+
+    pub fn resource_limits(mut self, resource_limits: Option<ResourceLimits>) -> Self {
+        self.resource_limits = resource_limits;
+        self
+    }
+
+    pub fn output_policy(mut self, output_policy: Option<OutputPolicy>) -> Self {
+        self.output_policy = output_policy;
+        self
+    }
+
     pub fn build(self, requestee: Uuid) -> Runner {
         Runner {
             id: self.id,
-            nonce: self.nonce.unwrap_or(String::new()),
+            nonce: self.nonce.unwrap_or_default(),
 
-            source: self
-                .source
-                .expect("[RUNNER-BUILD]: Expected value \"source\" to be non-null"),
+            source: self.source,
+            files: self.files.unwrap_or_default(),
+            archive: self.archive,
+            entrypoint: self.entrypoint,
             language: self
                 .language
                 .expect("[RUNNER-BUILD]: Expected value \"language\" to be non-null"),
+            mode: self.mode.unwrap_or_default(),
+            dependencies: self.dependencies.unwrap_or_default(),
+            artifacts: self.artifacts.unwrap_or_default(),
+            artifact_delivery: self.artifact_delivery.unwrap_or_default(),
+
+            commandline_arguments: self.commandline_arguments.unwrap_or_default(),
+            standard_input: self.standard_input.unwrap_or_default(),
+            interactive: self.interactive.unwrap_or(false),
+            output_framing: self.output_framing.unwrap_or_default(),
+            tty: self
+                .tty
+                .unwrap_or(false)
+                .then(|| self.window.unwrap_or_default()),
+            env: self.env.unwrap_or_default(),
 
-            commandline_arguments: self.commandline_arguments.unwrap_or(String::new()),
-            standard_input: self.standard_input.unwrap_or(String::new()),
+            time_limit_ms: self.time_limit_ms,
+            idle_timeout_ms: self.idle_timeout_ms,
+            resource_limits: self.resource_limits,
+            output_policy: self.output_policy,
 
             requestee,
             executor: None, // Has not been assigned an executor yet!
+            control: None,
         }
     }
 }
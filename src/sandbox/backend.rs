@@ -0,0 +1,468 @@
+use crate::lang::{Languages, RuntimeError};
+use crate::sandbox::{Monitor, Seccomp};
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::sync::Arc;
+
+/// Lays out the sandbox's root on a fresh tmpfs, then drops into it. Takes
+/// the init, root, uid, gid and working directory, the read-only paths and
+/// the writable ones each followed by `--`, then the program and its arguments.
+const UNSHARE_SETUP: &str = r#"set -e
+init=$1 root=$2 uid=$3 gid=$4 cwd=$5
+shift 5
+mount -t tmpfs -o mode=755 sandbox "$root"
+bind() {
+    if [ -d "$2" ]; then
+        mkdir -p "$root$2"
+    else
+        mkdir -p "$root$(dirname "$2")"
+        touch "$root$2"
+    fi
+    mount --rbind "$2" "$root$2"
+    if [ "$1" = ro ]; then mount -o remount,bind,ro "$root$2"; fi
+}
+while [ "$1" != -- ]; do
+    if [ -e "$1" ]; then bind ro "$1"; fi
+    shift
+done
+shift
+while [ "$1" != -- ]; do
+    bind rw "$1"
+    shift
+done
+shift
+mkdir -p "$root/tmp" "$root/proc" "$root/dev"
+mount -t tmpfs -o mode=1777 tmp "$root/tmp"
+mount -t proc proc "$root/proc"
+for device in null zero full random urandom tty; do
+    touch "$root/dev/$device"
+    mount --bind "/dev/$device" "$root/dev/$device"
+done
+if [ -n "$uid" ]; then
+    set -- setpriv --reuid="$uid" --regid="$gid" --clear-groups --no-new-privs -- "$@"
+fi
+exec chroot "$root" sh -c "$init" "$cwd" "$@""#;
+
+/// Stays behind as the namespace's init, which ignores signals it has no
+/// handler for, so that the program may still be signalled. Reports a program
+/// killed by signal `N` as exit status `128 + N`, as `bwrap` does.
+const INIT: &str = r#"cd "$0" || exit 1
+exec 3<&0
+"$@" <&3 3<&- &
+exec 3<&-
+wait $!"#;
+
+/// How a job's processes are confined
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backend {
+    /// Processes run directly on the host, as the server's user
+    #[default]
+    None,
+    /// New namespaces through `unshare(1)`, with the root laid out by hand
+    Unshare,
+    /// New namespaces through `bwrap`
+    Bubblewrap,
+}
+
+impl Backend {
+    pub fn from_string(backend: &str) -> Option<Self> {
+        match backend {
+            "none" => Some(Backend::None),
+            "unshare" => Some(Backend::Unshare),
+            "bwrap" => Some(Backend::Bubblewrap),
+            _ => None,
+        }
+    }
+}
+
+/// Confines every process a job starts to new user, PID, network, mount,
+/// IPC and UTS namespaces. Within them, a job sees only its own directories,
+/// read-only toolchain paths and a private `/tmp`.
+#[derive(Clone, Debug, Default)]
+pub struct Sandbox {
+    pub backend: Backend,
+    /// User and group jobs run as, where the server runs as root
+    pub uid: u32,
+    pub gid: u32,
+    /// Whether jobs may reach the network
+    pub network: bool,
+    /// Paths mounted read-only at the same place, skipped if missing
+    pub readonly: Vec<String>,
+    /// Empty directory each sandbox's root is mounted over, unshare only
+    pub root: String,
+    /// Syscall allowlists for each language's programs, builds are unfiltered
+    pub seccomp: Option<Arc<Seccomp>>,
+}
+
+impl Sandbox {
+    /// Rewrites `command`, a job's program, to run under its language's
+    /// seccomp filter. The [`Monitor`] learns of any syscall it refuses.
+    pub fn filter(
+        &self,
+        command: Command,
+        language: Languages,
+    ) -> Result<(Command, Option<Monitor>), RuntimeError> {
+        match &self.seccomp {
+            Some(seccomp) => {
+                let (command, monitor) = seccomp.filter(command, language)?;
+                Ok((command, Some(monitor)))
+            }
+            None => Ok((command, None)),
+        }
+    }
+
+    /// Rewrites `command` to run within the sandbox, with write access to
+    /// `writable` alone. Paths keep their place, so the command is unchanged
+    /// from within.
+    pub fn wrap(&self, command: Command, writable: &[&str]) -> Result<Command, RuntimeError> {
+        if self.backend == Backend::None {
+            return Ok(command);
+        }
+
+        let failed = |err: std::io::Error| RuntimeError::InitializationFailure(err.to_string());
+
+        let directory = command.get_current_dir().unwrap_or(Path::new("."));
+        let directory = fs::canonicalize(directory).map_err(failed)?;
+
+        let mut paths: Vec<PathBuf> = vec![];
+        for path in writable {
+            let path = fs::canonicalize(path).map_err(failed)?;
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        // SAFETY: `geteuid` has no preconditions.
+        let root = unsafe { libc::geteuid() } == 0;
+        if root {
+            for path in &paths {
+                chown(path, self.uid, self.gid).map_err(failed)?;
+            }
+        }
+
+        let mut wrapped = match self.backend {
+            Backend::None => return Ok(command),
+            Backend::Unshare => {
+                fs::create_dir_all(&self.root).map_err(failed)?;
+
+                let mut wrapped = Command::new("unshare");
+                wrapped.args([
+                    "--mount",
+                    "--propagation",
+                    "private",
+                    "--pid",
+                    "--fork",
+                    "--kill-child",
+                    "--ipc",
+                    "--uts",
+                ]);
+                if !self.network {
+                    wrapped.arg("--net");
+                }
+                if !root {
+                    wrapped.args(["--user", "--map-root-user"]);
+                }
+
+                let (uid, gid) = match root {
+                    true => (self.uid.to_string(), self.gid.to_string()),
+                    false => (String::new(), String::new()),
+                };
+
+                wrapped
+                    .args(["--", "sh", "-c", UNSHARE_SETUP, "sh", INIT])
+                    .args([&self.root, &uid, &gid])
+                    .arg(&directory)
+                    .args(&self.readonly)
+                    .arg("--")
+                    .args(&paths)
+                    .arg("--");
+                wrapped
+            }
+            Backend::Bubblewrap => {
+                // bwrap drops to the sandbox's user itself, with a user namespace
+                let mut wrapped = match root {
+                    true => {
+                        let mut wrapped = Command::new("setpriv");
+                        wrapped.args([
+                            format!("--reuid={}", self.uid),
+                            format!("--regid={}", self.gid),
+                            "--clear-groups".to_string(),
+                            "--".to_string(),
+                            "bwrap".to_string(),
+                        ]);
+                        wrapped
+                    }
+                    false => Command::new("bwrap"),
+                };
+
+                wrapped.args(["--die-with-parent", "--unshare-all"]);
+                if self.network {
+                    wrapped.arg("--share-net");
+                }
+                wrapped.args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+
+                for path in &self.readonly {
+                    wrapped.args(["--ro-bind-try", path, path]);
+                }
+                for path in &paths {
+                    wrapped.arg("--bind").arg(path).arg(path);
+                }
+
+                wrapped.arg("--chdir").arg(&directory).arg("--");
+                wrapped
+            }
+        };
+
+        wrapped
+            .arg(command.get_program())
+            .args(command.get_args())
+            .current_dir(&directory)
+            // Caches otherwise land in the home directory, which is not writable
+            .env("XDG_CACHE_HOME", "/tmp");
+
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => wrapped.env(key, value),
+                None => wrapped.env_remove(key),
+            };
+        }
+
+        Ok(wrapped)
+    }
+
+    /// The program's own exit status, from that of its sandbox
+    pub fn status(&self, status: ExitStatus) -> ExitStatus {
+        match (self.backend, status.code()) {
+            (Backend::None, _) => status,
+            (_, Some(code @ 129..=192)) => ExitStatus::from_raw(code - 128),
+            _ => status,
+        }
+    }
+}
+
+/// Hands `path`, and everything beneath it, to the sandbox's user
+fn chown(path: &Path, uid: u32, gid: u32) -> std::io::Result<()> {
+    let location = CString::new(path.as_os_str().as_bytes())?;
+
+    // SAFETY: `location` is a valid, NUL-terminated path.
+    if unsafe { libc::lchown(location.as_ptr(), uid, gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    if fs::symlink_metadata(path)?.is_dir() {
+        for entry in fs::read_dir(path)? {
+            chown(&entry?.path(), uid, gid)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, Sandbox, INIT, UNSHARE_SETUP};
+    use std::fs;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::Path;
+    use std::process::{Command, ExitStatus};
+    use uuid::Uuid;
+
+    /// A job's directory, with the command it runs from within it
+    fn program(directory: &Path) -> Command {
+        fs::create_dir_all(directory).unwrap();
+
+        let mut command = Command::new("./exec.out");
+        command
+            .args(["-n", "a b"])
+            .current_dir(directory)
+            .env("PYTHONPATH", ".packages");
+        command
+    }
+
+    fn sandbox(backend: Backend, network: bool) -> Sandbox {
+        Sandbox {
+            backend,
+            uid: 65534,
+            gid: 65534,
+            network,
+            readonly: vec!["/usr".to_string(), "/missing".to_string()],
+            root: std::env::temp_dir()
+                .join(format!("roadrunner-root-{}", Uuid::new_v4()))
+                .to_string_lossy()
+                .to_string(),
+            seccomp: None,
+        }
+    }
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|argument| argument.to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn root() -> bool {
+        // SAFETY: `geteuid` has no preconditions.
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    #[test]
+    fn leaves_commands_alone_without_a_backend() {
+        let directory = std::env::temp_dir().join(format!("roadrunner-{}", Uuid::new_v4()));
+        let command = sandbox(Backend::None, false)
+            .wrap(program(&directory), &[directory.to_str().unwrap()])
+            .unwrap();
+
+        assert_eq!(command.get_program(), "./exec.out");
+        assert_eq!(args(&command), ["-n", "a b"]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn builds_unshare_arguments() {
+        let directory = std::env::temp_dir().join(format!("roadrunner-{}", Uuid::new_v4()));
+        let job = directory.to_str().unwrap();
+        let sandbox = sandbox(Backend::Unshare, false);
+
+        // The same directory twice, once through a `.` component, is bound once
+        let command = sandbox
+            .wrap(program(&directory), &[job, &format!("{}/.", job)])
+            .unwrap();
+        let arguments = args(&command);
+        let directory = fs::canonicalize(&directory).unwrap();
+        let job = directory.to_str().unwrap();
+
+        let (uid, gid) = match root() {
+            true => ("65534", "65534"),
+            false => ("", ""),
+        };
+        let tail = [
+            "--",
+            "sh",
+            "-c",
+            UNSHARE_SETUP,
+            "sh",
+            INIT,
+            &sandbox.root,
+            uid,
+            gid,
+            job,
+            "/usr",
+            "/missing",
+            "--",
+            job,
+            "--",
+            "./exec.out",
+            "-n",
+            "a b",
+        ];
+
+        assert_eq!(command.get_program(), "unshare");
+        assert!(arguments.ends_with(&tail.map(str::to_string)));
+        assert!(arguments.contains(&"--net".to_string()));
+        assert_eq!(arguments.contains(&"--user".to_string()), !root());
+        assert_eq!(command.get_current_dir(), Some(directory.as_path()));
+        assert!(command
+            .get_envs()
+            .any(|(key, value)| key == "PYTHONPATH" && value == Some(".packages".as_ref())));
+
+        let networked = Sandbox {
+            network: true,
+            ..sandbox.clone()
+        };
+        let command = networked.wrap(program(&directory), &[job]).unwrap();
+        assert!(!args(&command).contains(&"--net".to_string()));
+
+        fs::remove_dir_all(&directory).unwrap();
+        fs::remove_dir_all(&sandbox.root).unwrap();
+    }
+
+    #[test]
+    fn builds_bwrap_arguments() {
+        let directory = std::env::temp_dir().join(format!("roadrunner-{}", Uuid::new_v4()));
+        let job = directory.to_str().unwrap().to_string();
+
+        let command = sandbox(Backend::Bubblewrap, false)
+            .wrap(program(&directory), &[&job])
+            .unwrap();
+        let arguments = args(&command);
+
+        // As root, the sandbox's user is dropped to before `bwrap` starts
+        match root() {
+            true => {
+                assert_eq!(command.get_program(), "setpriv");
+                assert_eq!(
+                    arguments[..5],
+                    [
+                        "--reuid=65534",
+                        "--regid=65534",
+                        "--clear-groups",
+                        "--",
+                        "bwrap"
+                    ]
+                );
+            }
+            false => assert_eq!(command.get_program(), "bwrap"),
+        }
+
+        let bwrap = arguments
+            .iter()
+            .position(|argument| argument == "--die-with-parent")
+            .unwrap();
+        let expected = [
+            "--die-with-parent",
+            "--unshare-all",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+            "--ro-bind-try",
+            "/usr",
+            "/usr",
+            "--ro-bind-try",
+            "/missing",
+            "/missing",
+            "--bind",
+            &job,
+            &job,
+            "--chdir",
+            &job,
+            "--",
+            "./exec.out",
+            "-n",
+            "a b",
+        ];
+        assert_eq!(arguments[bwrap..], expected.map(str::to_string));
+
+        let command = sandbox(Backend::Bubblewrap, true)
+            .wrap(program(&directory), &[&job])
+            .unwrap();
+        assert!(args(&command).contains(&"--share-net".to_string()));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn reports_signals_the_sandbox_passed_on() {
+        let sandbox = sandbox(Backend::Unshare, false);
+        let exited = |code: i32| ExitStatus::from_raw(code << 8);
+
+        assert_eq!(sandbox.status(exited(129)).signal(), Some(libc::SIGHUP));
+        assert_eq!(sandbox.status(exited(137)).signal(), Some(libc::SIGKILL));
+        assert_eq!(sandbox.status(exited(192)).signal(), Some(64));
+
+        for code in [0, 1, 128, 193, 255] {
+            assert_eq!(sandbox.status(exited(code)).code(), Some(code));
+        }
+
+        // Without a sandbox, the program's own status is already at hand
+        let unsandboxed = Sandbox::default();
+        assert_eq!(unsandboxed.status(exited(137)).code(), Some(137));
+    }
+}
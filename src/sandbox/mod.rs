@@ -0,0 +1,7 @@
+pub use backend::*;
+pub use seccomp::*;
+pub use syscalls::*;
+
+mod backend;
+mod seccomp;
+mod syscalls;
@@ -0,0 +1,680 @@
+use crate::lang::{Languages, RuntimeError};
+use crate::sandbox::{is_syscall, AUDIT_ARCH, SYSCALLS};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// First argument of the server's binary when started as a job's supervisor
+pub const SUPERVISOR: &str = "--seccomp-supervisor";
+
+/// Made by the supervisor in between installing the filter and starting the
+/// program, so always allowed
+const SUPERVISOR_SYSCALLS: [&str; 5] = ["close", "sendmsg", "execve", "exit", "exit_group"];
+
+const SECCOMP_SET_MODE_FILTER: libc::c_long = 1;
+const SECCOMP_FILTER_FLAG_NEW_LISTENER: libc::c_long = 1 << 3;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+/// `_IOWR('!', 0, struct seccomp_notif)`
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_JMP_JSET_K: u16 = 0x45;
+const BPF_RET_K: u16 = 0x06;
+/// Offsets into `struct seccomp_data`
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+/// Lower half of the first argument, on the little-endian hosts supported
+const DATA_ARG0: u32 = 16;
+
+/// `CLONE_NEW*` flags, a program may not enter new namespaces of its own
+const CLONE_NAMESPACES: u32 = (libc::CLONE_NEWNS
+    | libc::CLONE_NEWCGROUP
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUSER
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWNET) as u32;
+
+#[repr(C)]
+#[derive(Default)]
+struct SeccompData {
+    nr: i32,
+    arch: u32,
+    instruction_pointer: u64,
+    args: [u64; 6],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct SeccompNotif {
+    id: u64,
+    pid: u32,
+    flags: u32,
+    data: SeccompData,
+}
+
+/// A language's profile, `seccomp/{language}.json`
+#[derive(Deserialize)]
+struct Profile {
+    /// Profile whose syscalls are allowed as well
+    extends: Option<String>,
+    #[serde(default)]
+    syscalls: Vec<String>,
+}
+
+/// Syscall allowlists of each language, compiled from the profiles in a
+/// directory. Languages without a profile of their own use `default.json`.
+#[derive(Clone, Debug)]
+pub struct Seccomp {
+    /// Base64 encoded `sock_filter`s of each language
+    filters: HashMap<Languages, String>,
+    /// The server's own binary, which supervises filtered programs
+    pub supervisor: PathBuf,
+}
+
+impl Seccomp {
+    /// Without `network`, programs may only open sockets of their own, `AF_UNIX`
+    pub fn load(directory: &str, network: bool) -> Result<Self, String> {
+        let supervisor = std::env::current_exe().map_err(|err| err.to_string())?;
+
+        let mut filters = HashMap::new();
+        for language in Languages::ALL {
+            let name = match Path::new(directory)
+                .join(format!("{}.json", language.as_string()))
+                .exists()
+            {
+                true => language.as_string(),
+                false => "default",
+            };
+
+            let mut syscalls = allowed(directory, name, &mut vec![])?;
+            syscalls.extend(SUPERVISOR_SYSCALLS.map(str::to_string));
+
+            // Those of another architecture are left out, anything else is a mistake
+            let mut numbers = vec![];
+            for syscall in &syscalls {
+                match number(syscall) {
+                    Some(nr) => numbers.push(nr),
+                    None if is_syscall(syscall) => {}
+                    None => {
+                        return Err(format!(
+                            "Profile '{}' names unknown syscall '{}'",
+                            name, syscall
+                        ))
+                    }
+                }
+            }
+            numbers.sort_unstable();
+            numbers.dedup();
+
+            filters.insert(language, STANDARD.encode(compile(&numbers, network)));
+        }
+
+        Ok(Seccomp {
+            filters,
+            supervisor,
+        })
+    }
+
+    /// Rewrites `command` to start under the supervisor, which installs the
+    /// language's filter just before the program itself is executed.
+    pub fn filter(
+        &self,
+        command: Command,
+        language: Languages,
+    ) -> Result<(Command, Monitor), RuntimeError> {
+        let monitor =
+            Monitor::new().map_err(|err| RuntimeError::InitializationFailure(err.to_string()))?;
+
+        let mut wrapped = Command::new(&self.supervisor);
+        wrapped
+            .arg(SUPERVISOR)
+            .arg(monitor.report.as_raw_fd().to_string())
+            .arg(&self.filters[&language])
+            .arg("--")
+            .arg(command.get_program())
+            .args(command.get_args());
+
+        if let Some(directory) = command.get_current_dir() {
+            wrapped.current_dir(directory);
+        }
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => wrapped.env(key, value),
+                None => wrapped.env_remove(key),
+            };
+        }
+
+        Ok((wrapped, monitor))
+    }
+}
+
+/// Syscalls allowed by the profile `name` and those it extends
+fn allowed(directory: &str, name: &str, seen: &mut Vec<String>) -> Result<Vec<String>, String> {
+    if seen.iter().any(|profile| profile == name) {
+        return Err(format!("Profile '{}' extends itself", name));
+    }
+    seen.push(name.to_string());
+
+    let path = Path::new(directory).join(format!("{}.json", name));
+    let profile = fs::read_to_string(&path)
+        .map_err(|err| format!("Could not read '{}': {}", path.display(), err))?;
+    let profile: Profile = serde_json::from_str(&profile)
+        .map_err(|err| format!("Could not parse '{}': {}", path.display(), err))?;
+
+    let mut syscalls = profile.syscalls;
+    if let Some(parent) = &profile.extends {
+        syscalls.extend(allowed(directory, parent, seen)?);
+    }
+
+    Ok(syscalls)
+}
+
+fn number(syscall: &str) -> Option<u32> {
+    SYSCALLS
+        .iter()
+        .find(|(name, _)| *name == syscall)
+        .map(|(_, nr)| *nr)
+}
+
+fn name(nr: u32) -> Option<&'static str> {
+    SYSCALLS
+        .iter()
+        .find(|(_, number)| *number == nr)
+        .map(|(name, _)| *name)
+}
+
+/// Allows each of `numbers`, handing anything else, syscalls of other ABIs
+/// included, to the supervisor. `clone` is only allowed without namespace
+/// flags, and `clone3`, whose flags a filter cannot read, fails with `ENOSYS`
+/// so that the C library falls back to `clone`. Without `network`, `socket`
+/// fails with `EACCES` for any family but `AF_UNIX`, as though disconnected.
+fn compile(numbers: &[u32], network: bool) -> Vec<u8> {
+    let mut program: Vec<(u16, u8, u8, u32)> = vec![
+        (BPF_LD_W_ABS, 0, 0, DATA_ARCH),
+        (BPF_JMP_JEQ_K, 1, 0, AUDIT_ARCH),
+        (BPF_RET_K, 0, 0, SECCOMP_RET_USER_NOTIF),
+        (BPF_LD_W_ABS, 0, 0, DATA_NR),
+    ];
+
+    for nr in numbers {
+        match name(*nr) {
+            Some("clone") => {
+                program.push((BPF_JMP_JEQ_K, 0, 4, *nr));
+                program.push((BPF_LD_W_ABS, 0, 0, DATA_ARG0));
+                program.push((BPF_JMP_JSET_K, 1, 0, CLONE_NAMESPACES));
+                program.push((BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW));
+                program.push((BPF_RET_K, 0, 0, SECCOMP_RET_USER_NOTIF));
+            }
+            Some("socket") if !network => {
+                program.push((BPF_JMP_JEQ_K, 0, 4, *nr));
+                program.push((BPF_LD_W_ABS, 0, 0, DATA_ARG0));
+                program.push((BPF_JMP_JEQ_K, 0, 1, libc::AF_UNIX as u32));
+                program.push((BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW));
+                program.push((BPF_RET_K, 0, 0, SECCOMP_RET_ERRNO | libc::EACCES as u32));
+            }
+            Some("clone3") => {
+                program.push((BPF_JMP_JEQ_K, 0, 1, *nr));
+                program.push((BPF_RET_K, 0, 0, SECCOMP_RET_ERRNO | libc::ENOSYS as u32));
+            }
+            _ => {
+                program.push((BPF_JMP_JEQ_K, 0, 1, *nr));
+                program.push((BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW));
+            }
+        }
+    }
+    program.push((BPF_RET_K, 0, 0, SECCOMP_RET_USER_NOTIF));
+
+    let mut encoded = Vec::with_capacity(program.len() * 8);
+    for (code, jt, jf, k) in program {
+        encoded.extend(code.to_ne_bytes());
+        encoded.extend([jt, jf]);
+        encoded.extend(k.to_ne_bytes());
+    }
+    encoded
+}
+
+/// Receives the number of a syscall outside of the program's allowlist,
+/// should it make one.
+pub struct Monitor {
+    violations: OwnedFd,
+    report: OwnedFd,
+}
+
+impl Monitor {
+    fn new() -> std::io::Result<Self> {
+        let mut fds = [0; 2];
+
+        // SAFETY: `fds` has room for both ends of the pipe.
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // SAFETY: `pipe2` returned both ends, which nothing else owns.
+        let (violations, report) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        // Kept clear of the low descriptors the sandbox's shell redirects
+        // SAFETY: `report` is open, `fcntl` returns a new descriptor or -1.
+        let moved = unsafe { libc::fcntl(report.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 10) };
+        if moved < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Monitor {
+            violations,
+            // SAFETY: `moved` was just opened, and is owned by nothing else.
+            report: unsafe { OwnedFd::from_raw_fd(moved) },
+        })
+    }
+
+    /// Lets the supervisor, started by `command`, inherit the reporting end
+    pub fn inherit(&self, command: &mut Command) {
+        let report = self.report.as_raw_fd();
+
+        // SAFETY: Only `fcntl` is called, which is async-signal-safe.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::fcntl(report, libc::F_SETFD, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Syscall the program was stopped for, once it has exited
+    pub fn violation(&self) -> Option<&'static str> {
+        let mut buffer = [0u8; 32];
+
+        // SAFETY: `buffer` is writable for its whole length.
+        let read = unsafe {
+            libc::read(
+                self.violations.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if read <= 0 {
+            return None;
+        }
+
+        let report = String::from_utf8_lossy(&buffer[..read as usize]);
+        let syscall = report
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .and_then(name)
+            .unwrap_or("unknown");
+        Some(syscall)
+    }
+}
+
+/// Arguments of a supervisor, `SUPERVISOR`, when the binary was started as one
+pub fn supervisor_args() -> Option<Vec<OsString>> {
+    let mut args = std::env::args_os().skip(1);
+    match args.next() {
+        Some(first) if first == SUPERVISOR => Some(args.collect()),
+        _ => None,
+    }
+}
+
+/// Runs the program under a filter, taking `report filter -- program args...`.
+/// Reports the first syscall the filter refuses, then kills the program and
+/// anything it started. Otherwise, exits as the program did.
+pub fn supervise(args: Vec<OsString>) -> ! {
+    let fail = |message: String| -> ! {
+        eprintln!("roadrunner_error: {}", message);
+        std::process::exit(126)
+    };
+
+    let (report, filter, program) = match args.as_slice() {
+        [report, filter, separator, program @ ..] if separator == "--" && !program.is_empty() => {
+            (report, filter, program)
+        }
+        _ => fail("Supervisor started without a program".to_string()),
+    };
+
+    let report: RawFd = report
+        .to_str()
+        .and_then(|report| report.parse().ok())
+        .unwrap_or_else(|| fail("Supervisor started without a report descriptor".to_string()));
+    let filter = STANDARD
+        .decode(filter.as_bytes())
+        .unwrap_or_else(|err| fail(format!("Malformed filter, {}", err)));
+
+    let mut instructions: Vec<libc::sock_filter> = filter
+        .chunks_exact(8)
+        .map(|chunk| libc::sock_filter {
+            code: u16::from_ne_bytes([chunk[0], chunk[1]]),
+            jt: chunk[2],
+            jf: chunk[3],
+            k: u32::from_ne_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]),
+        })
+        .collect();
+    let prog = libc::sock_fprog {
+        len: instructions.len() as u16,
+        filter: instructions.as_mut_ptr(),
+    };
+
+    let argv: Vec<CString> = program
+        .iter()
+        .map(|arg| CString::new(arg.as_bytes()).unwrap_or_else(|_| fail("NUL in argument".into())))
+        .collect();
+    let mut argv_ptrs: Vec<*const libc::c_char> = argv.iter().map(|arg| arg.as_ptr()).collect();
+    argv_ptrs.push(std::ptr::null());
+
+    let mut sockets = [0; 2];
+    // SAFETY: `sockets` has room for both ends of the pair.
+    if unsafe {
+        libc::socketpair(
+            libc::AF_UNIX,
+            libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+            0,
+            sockets.as_mut_ptr(),
+        )
+    } != 0
+    {
+        fail(format!("socketpair: {}", std::io::Error::last_os_error()));
+    }
+
+    // Signals meant for the job are left to the program, which the supervisor outlives
+    const FORWARDED: [libc::c_int; 4] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP, libc::SIGQUIT];
+
+    // SAFETY: The supervisor is single-threaded, so the child may run any
+    // code until it executes the program.
+    unsafe {
+        for signal in FORWARDED {
+            libc::signal(signal, libc::SIG_IGN);
+        }
+
+        let child = libc::fork();
+        if child < 0 {
+            fail(format!("fork: {}", std::io::Error::last_os_error()));
+        }
+
+        if child == 0 {
+            for signal in FORWARDED {
+                libc::signal(signal, libc::SIG_DFL);
+            }
+            libc::close(report);
+            libc::close(sockets[0]);
+
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                libc::_exit(126);
+            }
+            let listener = libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                &prog as *const libc::sock_fprog,
+            ) as RawFd;
+            if listener < 0 {
+                let message = b"roadrunner_error: Could not install the seccomp filter\n";
+                libc::write(2, message.as_ptr() as *const libc::c_void, message.len());
+                libc::_exit(126);
+            }
+
+            if send_fd(sockets[1], listener).is_err() {
+                libc::_exit(126);
+            }
+            libc::close(listener);
+            libc::close(sockets[1]);
+
+            libc::execvp(argv_ptrs[0], argv_ptrs.as_ptr());
+            libc::_exit(127);
+        }
+
+        libc::close(sockets[1]);
+        let mut listener = receive_fd(sockets[0]);
+        libc::close(sockets[0]);
+
+        let pidfd = libc::syscall(libc::SYS_pidfd_open, child, 0) as RawFd;
+        // Without a pidfd, the program's exit is checked for now and then
+        let wait = if pidfd < 0 { 100 } else { -1 };
+
+        loop {
+            let mut fds = [
+                libc::pollfd {
+                    fd: listener.unwrap_or(-1),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: pidfd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, wait);
+
+            if let Some(fd) = listener {
+                if fds[0].revents & libc::POLLIN != 0 {
+                    let mut notification = SeccompNotif::default();
+                    if libc::ioctl(fd, SECCOMP_IOCTL_NOTIF_RECV, &mut notification) == 0 {
+                        let nr = match notification.data.arch == AUDIT_ARCH {
+                            true => notification.data.nr,
+                            false => -1,
+                        };
+                        let line = format!("{}\n", nr);
+                        libc::write(report, line.as_ptr() as *const libc::c_void, line.len());
+
+                        libc::kill(notification.pid as libc::pid_t, libc::SIGKILL);
+                        libc::killpg(0, libc::SIGKILL);
+                    }
+                } else if fds[0].revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+                    // Nothing is left under the filter
+                    listener = None;
+                }
+            }
+
+            let mut status = 0;
+            if libc::waitpid(child, &mut status, libc::WNOHANG) == child {
+                if libc::WIFSIGNALED(status) {
+                    let signal = libc::WTERMSIG(status);
+                    libc::signal(signal, libc::SIG_DFL);
+                    libc::kill(libc::getpid(), signal);
+                    libc::_exit(128 + signal);
+                }
+                libc::_exit(libc::WEXITSTATUS(status));
+            }
+        }
+    }
+}
+
+/// Passes `fd` over the socket `socket`
+unsafe fn send_fd(socket: RawFd, fd: RawFd) -> Result<(), ()> {
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: &mut byte as *mut u8 as *mut libc::c_void,
+        iov_len: 1,
+    };
+    let mut control = [0u8; 64];
+
+    let mut message: libc::msghdr = std::mem::zeroed();
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) as _;
+
+    let header = libc::CMSG_FIRSTHDR(&message);
+    (*header).cmsg_level = libc::SOL_SOCKET;
+    (*header).cmsg_type = libc::SCM_RIGHTS;
+    (*header).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+    std::ptr::write_unaligned(libc::CMSG_DATA(header) as *mut RawFd, fd);
+
+    match libc::sendmsg(socket, &message, 0) {
+        1 => Ok(()),
+        _ => Err(()),
+    }
+}
+
+/// Receives a descriptor passed by [`send_fd`], if one arrives
+unsafe fn receive_fd(socket: RawFd) -> Option<RawFd> {
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: &mut byte as *mut u8 as *mut libc::c_void,
+        iov_len: 1,
+    };
+    let mut control = [0u8; 64];
+
+    let mut message: libc::msghdr = std::mem::zeroed();
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = control.len() as _;
+
+    if libc::recvmsg(socket, &mut message, libc::MSG_CMSG_CLOEXEC) != 1 {
+        return None;
+    }
+
+    let header = libc::CMSG_FIRSTHDR(&message);
+    if header.is_null() || (*header).cmsg_type != libc::SCM_RIGHTS {
+        return None;
+    }
+    Some(std::ptr::read_unaligned(
+        libc::CMSG_DATA(header) as *const RawFd
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        allowed, compile, number, Seccomp, BPF_JMP_JEQ_K, BPF_LD_W_ABS, BPF_RET_K, DATA_ARG0,
+        SECCOMP_RET_ALLOW, SECCOMP_RET_ERRNO,
+    };
+    use crate::lang::Languages;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Instructions of a compiled program, as `(code, jt, jf, k)`
+    fn decode(program: &[u8]) -> Vec<(u16, u8, u8, u32)> {
+        program
+            .chunks_exact(8)
+            .map(|chunk| {
+                (
+                    u16::from_ne_bytes([chunk[0], chunk[1]]),
+                    chunk[2],
+                    chunk[3],
+                    u32::from_ne_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]),
+                )
+            })
+            .collect()
+    }
+
+    /// A directory of `profiles`, removed by the caller
+    fn profiles(profiles: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("roadrunner-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, profile) in profiles {
+            fs::write(directory.join(format!("{}.json", name)), profile).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn loads_the_shipped_profiles() {
+        let seccomp = Seccomp::load("seccomp", false).unwrap();
+        for language in Languages::ALL {
+            assert!(!seccomp.filters[&language].is_empty());
+        }
+    }
+
+    #[test]
+    fn merges_the_profiles_extended() {
+        let syscalls = allowed("seccomp", "c", &mut vec![]).unwrap();
+        assert!(syscalls.iter().any(|syscall| syscall == "shmget"));
+        assert!(syscalls.iter().any(|syscall| syscall == "read"));
+
+        let syscalls = allowed("seccomp", "default", &mut vec![]).unwrap();
+        assert!(!syscalls.iter().any(|syscall| syscall == "shmget"));
+    }
+
+    #[test]
+    fn refuses_unknown_syscalls_and_cycles() {
+        let directory = profiles(&[
+            ("default", r#"{"syscalls": ["read", "reed"]}"#),
+            ("c", r#"{"extends": "cpp", "syscalls": []}"#),
+            ("cpp", r#"{"extends": "c", "syscalls": []}"#),
+        ]);
+        let path = directory.to_str().unwrap();
+
+        let unknown = Seccomp::load(path, false).unwrap_err();
+        assert!(unknown.contains("'reed'"), "{}", unknown);
+
+        let cycle = allowed(path, "c", &mut vec![]).unwrap_err();
+        assert!(cycle.contains("extends itself"), "{}", cycle);
+
+        // Those of the other architecture are left out instead
+        fs::write(
+            directory.join("default.json"),
+            r#"{"syscalls": ["read", "open", "renameat2"]}"#,
+        )
+        .unwrap();
+        fs::remove_file(directory.join("c.json")).unwrap();
+        fs::remove_file(directory.join("cpp.json")).unwrap();
+        let loaded = Seccomp::load(path, false);
+
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(loaded.is_ok());
+    }
+
+    #[test]
+    fn jumps_past_each_syscall() {
+        let names = ["read", "clone", "clone3", "socket"];
+        let numbers: Vec<u32> = names.iter().map(|name| number(name).unwrap()).collect();
+
+        let program = decode(&compile(&numbers, true));
+        // Prologue, then 2 for each syscall, 5 for `clone`, and the final return
+        assert_eq!(program.len(), 4 + 2 + 5 + 2 + 2 + 1);
+
+        let mut at = 4;
+        for (name, nr) in names.iter().zip(&numbers) {
+            let (_, jt, jf, k) = program[at];
+            assert_eq!((jt, k), (0, *nr), "{}", name);
+
+            // Each check falls through to its own return, or jumps to the next check
+            let next = at + 1 + jf as usize;
+            assert_eq!(program[next - 1].0, BPF_RET_K, "{}", name);
+            at = next;
+        }
+        assert_eq!(at, program.len() - 1);
+        assert_eq!(program[5], (BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW));
+    }
+
+    #[test]
+    fn confines_sockets_without_a_network() {
+        let socket = number("socket").unwrap();
+
+        let program = decode(&compile(&[socket], true));
+        assert_eq!(
+            program[4..6],
+            [
+                (BPF_JMP_JEQ_K, 0, 1, socket),
+                (BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW)
+            ]
+        );
+
+        let program = decode(&compile(&[socket], false));
+        assert_eq!(
+            program[4..9],
+            [
+                (BPF_JMP_JEQ_K, 0, 4, socket),
+                (BPF_LD_W_ABS, 0, 0, DATA_ARG0),
+                (BPF_JMP_JEQ_K, 0, 1, libc::AF_UNIX as u32),
+                (BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW),
+                (BPF_RET_K, 0, 0, SECCOMP_RET_ERRNO | libc::EACCES as u32),
+            ]
+        );
+    }
+}
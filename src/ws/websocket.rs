@@ -1,9 +1,14 @@
 use std::sync::Arc;
 
 use crate::{
-    lang::RuntimeError,
-    runner::{Client, ExecutePacket, GlobalState, Locked, RunnerBuilder},
+    exec::{
+        Arguments, Environment, ExecutorControl, TerminalStream, TerminalStreamType, WindowSize,
+    },
+    lang::{ExecutionOutput, Languages, RuntimeError},
+    pool::Pool,
+    runner::{Client, ControlPacket, ExecutePacket, GlobalState, Locked, RunnerBuilder},
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
 use futures::{SinkExt, StreamExt};
 use tokio::sync::{
     mpsc::{self},
@@ -73,42 +78,266 @@ async fn client_connection(ws: WebSocket, config: Locked<GlobalState>) {
         println!("[POOL]: Cleaned Directory for user-leave")
     }
 
-    config.lock().await.clients.lock().await.remove(id);
+    let config_lock = config.lock().await;
+    config_lock.clients.lock().await.remove(id);
+    config_lock
+        .runners
+        .lock()
+        .await
+        .retain(|_, runner| runner.requestee != client.id);
 }
 
 async fn client_msg(client: Client, msg: Message, config: &Locked<GlobalState>) {
-    // Expect message to be of type -> Insert Runner
-    let string = msg.to_str().unwrap();
+    if msg.is_binary() {
+        archive_msg(client, msg.as_bytes(), config).await;
+        return;
+    }
+
+    // Pings and pongs are answered by warp itself
+    let Ok(string) = msg.to_str() else {
+        return;
+    };
 
-    let packet: ExecutePacket = match serde_json::from_str(string) {
+    if let Ok(control) = serde_json::from_str::<ControlPacket>(string) {
+        control_msg(client, control, config).await;
+        return;
+    }
+
+    let mut packet: ExecutePacket = match serde_json::from_str(string) {
         Ok(val) => val,
         Err(err) => {
             println!("[WS]: Error parsing input, {}", err);
-            client
-                .sender
-                .send(Message::text(
-                    serde_json::to_string(&RuntimeError::ParseInput(err.to_string())).unwrap(),
-                ))
-                .unwrap();
+            reject(&client, RuntimeError::ParseInput(err.to_string()));
+            return;
+        }
+    };
+
+    let archive = match packet
+        .archive
+        .take()
+        .map(|archive| STANDARD.decode(archive))
+    {
+        Some(Ok(archive)) => Some(archive),
+        Some(Err(err)) => {
+            reject(
+                &client,
+                RuntimeError::ParseInput(format!("`archive` is not valid base64, {}", err)),
+            );
+            return;
+        }
+        None => None,
+    };
+
+    execute_msg(client, packet, archive, config).await;
+}
+
+/// A binary frame holds the length of its header as a big-endian `u32`, the
+/// header itself as an [`ExecutePacket`] in JSON, then the archive's bytes.
+async fn archive_msg(client: Client, frame: &[u8], config: &Locked<GlobalState>) {
+    let header = frame
+        .get(..4)
+        .map(|length| u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize)
+        .and_then(|length| Some((frame.get(4..4 + length)?, &frame[4 + length..])));
 
+    let Some((header, archive)) = header else {
+        reject(
+            &client,
+            RuntimeError::ParseInput("Binary frame is shorter than its header".to_string()),
+        );
+        return;
+    };
+
+    match serde_json::from_slice::<ExecutePacket>(header) {
+        Ok(packet) => execute_msg(client, packet, Some(archive.to_vec()), config).await,
+        Err(err) => {
+            println!("[WS]: Error parsing input, {}", err);
+            reject(&client, RuntimeError::ParseInput(err.to_string()));
+        }
+    }
+}
+
+async fn execute_msg(
+    client: Client,
+    packet: ExecutePacket,
+    archive: Option<Vec<u8>>,
+    config: &Locked<GlobalState>,
+) {
+    if packet.source.is_none() && packet.files.is_none() && archive.is_none() {
+        reject(
+            &client,
+            RuntimeError::ParseInput("Expected either `source`, `files` or `archive`".to_string()),
+        );
+        return;
+    }
+
+    let invalid = packet
+        .env
+        .iter()
+        .flatten()
+        .find(|(key, value)| !Environment::permits(key, value));
+    if let Some((key, _)) = invalid {
+        reject(
+            &client,
+            RuntimeError::ParseInput(format!("`env` holds an invalid variable, '{}'", key)),
+        );
+        return;
+    }
+
+    let language = match Languages::resolve(
+        packet.language.as_deref(),
+        packet.entrypoint.as_deref(),
+        &packet.nonce,
+    ) {
+        Ok(language) => language,
+        Err(err) => {
+            reject(&client, err);
+            return;
+        }
+    };
+
+    let arguments = match packet.commandline_arguments.map(Arguments::parse) {
+        Some(Ok(arguments)) => Some(arguments),
+        Some(Err(err)) => {
+            reject(&client, err);
             return;
         }
+        None => None,
     };
 
-    let runner = RunnerBuilder::new()
-        .arguments(packet.commandline_arguments)
+    let mut runner = RunnerBuilder::new()
+        .arguments(arguments)
         .input(packet.standard_input)
-        .language(&packet.language)
+        .interactive(packet.interactive)
+        .output_framing(packet.output_framing)
+        .tty(packet.tty)
+        .window(packet.window)
+        .env(packet.env)
+        .time_limit(packet.time_limit_ms)
+        .idle_timeout(packet.idle_timeout_ms)
+        .resource_limits(packet.resource_limits)
+        .output_policy(packet.output_policy)
+        .language(language)
+        .mode(packet.mode)
+        .dependencies(packet.dependencies)
+        .artifacts(packet.artifacts)
+        .artifact_delivery(packet.artifact_delivery)
         .source(packet.source)
+        .files(packet.files)
+        .archive(archive)
+        .entrypoint(packet.entrypoint)
         .nonce(packet.nonce)
         .build(client.id);
 
-    let executor = runner.batch();
-    config
+    let config_lock = config.lock().await;
+
+    let executor = runner.clone().batch(&config_lock.settings);
+    runner.assign(&executor);
+
+    config_lock
+        .runners
         .lock()
         .await
+        .insert(runner.id.to_string(), runner);
+    config_lock
         .task_queue
         .lock()
         .await
         .push_back(Arc::new(Mutex::new(executor)));
+    config_lock.task_notifier.notify_one();
+
+    Pool::announce_positions(&config_lock).await;
+}
+
+async fn control_msg(client: Client, packet: ControlPacket, config: &Locked<GlobalState>) {
+    let nonce = packet.nonce().to_string();
+
+    if let ControlPacket::Cancel { .. } = packet {
+        if dequeue(&client, &nonce, config).await {
+            let output = TerminalStream::new_output(
+                TerminalStreamType::EndOfOutput,
+                ExecutionOutput::cancelled(),
+                Some(nonce),
+            );
+
+            client
+                .sender
+                .send(Message::text(serde_json::to_string(&output).unwrap()))
+                .unwrap();
+
+            return;
+        }
+    }
+
+    let control = config
+        .lock()
+        .await
+        .runners
+        .lock()
+        .await
+        .values()
+        .find(|runner| runner.requestee == client.id && runner.nonce == nonce)
+        .and_then(|runner| runner.control.clone());
+
+    let instruction = match packet {
+        ControlPacket::Stdin { data, .. } => ExecutorControl::StandardInput(data),
+        ControlPacket::CloseStdin { .. } => ExecutorControl::CloseInput,
+        ControlPacket::Cancel { .. } => ExecutorControl::Cancel,
+        ControlPacket::Resize { rows, columns, .. } => {
+            ExecutorControl::Resize(WindowSize { rows, columns })
+        }
+    };
+
+    let delivered = match control {
+        Some(control) => control.send(instruction).is_ok(),
+        None => false,
+    };
+
+    // The job may have finished between the client sending and us receiving
+    if !delivered {
+        client
+            .sender
+            .send(Message::text(RuntimeError::UnknownJob(nonce).as_string()))
+            .unwrap();
+    }
+}
+
+fn reject(client: &Client, error: RuntimeError) {
+    client
+        .sender
+        .send(Message::text(error.as_string()))
+        .unwrap();
+}
+
+/// Removes a job which has not yet been started from the queue, returning
+/// whether one was found.
+async fn dequeue(client: &Client, nonce: &str, config: &Locked<GlobalState>) -> bool {
+    let config_lock = config.lock().await;
+    let mut task_queue = config_lock.task_queue.lock().await;
+
+    let mut position = None;
+    for (index, task) in task_queue.iter().enumerate() {
+        let unlocked = task.lock().await;
+
+        if unlocked.sender_id == client.id && unlocked.nonce.as_deref() == Some(nonce) {
+            position = Some((index, unlocked.id));
+            break;
+        }
+    }
+
+    let Some((index, id)) = position else {
+        return false;
+    };
+
+    task_queue.remove(index);
+    drop(task_queue);
+
+    Pool::announce_positions(&config_lock).await;
+
+    config_lock
+        .runners
+        .lock()
+        .await
+        .retain(|_, runner| runner.executor != Some(id));
+
+    true
 }
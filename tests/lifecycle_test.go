@@ -0,0 +1,94 @@
+package main_test
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+)
+
+func (suite *RoadRunnerTestSuite) TestInteractiveInput() {
+	var outputs []string
+
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "StandardOutput" {
+			outputs = append(outputs, response.PipeValue)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.Equal(t, []string{"echo: first", "echo: second"}, outputs)
+			assert.Equal(t, "exit status: 0", response.Value.ExitStatus)
+		}
+	}
+
+	testConversation(suite, []byte(`{
+		"language": "python",
+		"source": "import sys\nfor line in sys.stdin:\n\tprint('echo: ' + line.strip())",
+		"nonce": "interactive-input",
+		"interactive": true
+	}`), [][]byte{
+		[]byte(`{"type": "stdin", "nonce": "interactive-input", "data": "first\n"}`),
+		[]byte(`{"type": "stdin", "nonce": "interactive-input", "data": "second\n"}`),
+		[]byte(`{"type": "close_stdin", "nonce": "interactive-input"}`),
+	}, assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestCancel() {
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "StandardOutput" {
+			t.Error("Cancelled job wrote output", response.PipeValue)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.JSONEq(t, `"Cancelled"`, string(response.Value.Reason))
+		}
+	}
+
+	testConversation(suite, []byte(`{
+		"language": "python",
+		"source": "import time\ntime.sleep(30)\nprint('finished')",
+		"nonce": "cancel-running"
+	}`), [][]byte{
+		[]byte(`{"type": "cancel", "nonce": "cancel-running"}`),
+	}, assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestTimeLimit() {
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "EndOfOutput" {
+			assert.JSONEq(t, `"TimeLimitExceeded"`, string(response.Value.Reason))
+			assert.Equal(t, "signal: 15 (SIGTERM)", response.Value.ExitStatus)
+		}
+	}
+
+	testHeader(suite, []byte(`{
+		"language": "python",
+		"source": "while True:\n\tpass",
+		"nonce": "time-limit",
+		"time_limit_ms": 1000
+	}`), assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestLifecycleEvents() {
+	var events []string
+
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "Queued" {
+			assert.GreaterOrEqual(t, response.Position, 1)
+		}
+
+		// Queued may be resent as the queue moves
+		if len(events) == 0 || events[len(events)-1] != response.TerminalType {
+			events = append(events, response.TerminalType)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.Equal(t, []string{"Queued", "Running", "StandardOutput", "Finished", "EndOfOutput"}, events)
+		}
+	}
+
+	testHeader(suite, []byte(`{
+		"language": "python",
+		"source": "print('hello')",
+		"nonce": "lifecycle-events"
+	}`), assertionFunction)
+}
@@ -20,24 +20,31 @@ type RoadRunnerTestSuite struct {
 }
 
 type RoadRunnerTermination struct {
-	ExitStatus string      `json:"exit_status"`
-	Duration   json.Number `json:"duration"`
+	ExitStatus string          `json:"exit_status"`
+	Duration   json.Number     `json:"duration"`
+	Reason     json.RawMessage `json:"reason"`
 }
 
 type RoadRunnerResponse struct {
 	TerminalType string                `json:"terminal_type"`
 	Value        RoadRunnerTermination `json:"value"`
 	PipeValue    string                `json:"pipe_value"`
+	Position     int                   `json:"position"`
 	Nonce        string                `json:"nonce"`
 	Timestamp    string                `json:"timestamp"`
 }
 
 type RoadRunnerRequest struct {
-	Language             string `json:"language"`
-	Source               string `json:"source"`
-	Nonce                string `json:"nonce"`
-	StandardInput        string `json:"standard_input"`
-	CommandLineArguments string `json:"commandline_arguments"`
+	Language             string          `json:"language"`
+	Source               string          `json:"source"`
+	Nonce                string          `json:"nonce"`
+	StandardInput        string          `json:"standard_input"`
+	CommandLineArguments json.RawMessage `json:"commandline_arguments"`
+}
+
+type RoadRunnerError struct {
+	Type  string          `json:"type"`
+	Value json.RawMessage `json:"value"`
 }
 
 var done chan interface{}
@@ -78,6 +85,11 @@ func closeWebsocketConnection(conn *websocket.Conn) {
 }
 
 func testHeader(suite *RoadRunnerTestSuite, content []byte, assertion func(response RoadRunnerResponse, t *testing.T)) {
+	testConversation(suite, content, nil, assertion)
+}
+
+// Sends the job, then each of the follow-up messages once it is running
+func testConversation(suite *RoadRunnerTestSuite, content []byte, followUps [][]byte, assertion func(response RoadRunnerResponse, t *testing.T)) {
 	// Channel for receiving the response
 	responseCh := make(chan RoadRunnerResponse)
 	doneCh := make(chan struct{})
@@ -129,6 +141,13 @@ func testHeader(suite *RoadRunnerTestSuite, content []byte, assertion func(respo
 				assert.NoError(t, err)
 
 				if myResponse.Nonce == referenceNonce.Nonce {
+					if myResponse.TerminalType == "Running" {
+						for _, followUp := range followUps {
+							err = conn.WriteMessage(websocket.TextMessage, followUp)
+							assert.NoError(t, err)
+						}
+					}
+
 					responseCh <- myResponse
 
 					if myResponse.TerminalType == "EndOfOutput" {
@@ -166,6 +185,33 @@ out:
 	<-doneCh
 }
 
+// Sends a job which is refused before it is queued, such errors carry no nonce of their own
+func testRejection(suite *RoadRunnerTestSuite, content []byte, assertion func(rejection RoadRunnerError, t *testing.T)) {
+	t := suite.T()
+	conn := instantiateWebsocketConnection()
+	defer closeWebsocketConnection(conn)
+
+	err := conn.WriteMessage(websocket.TextMessage, content)
+	assert.NoError(t, err)
+
+	err = conn.SetReadDeadline(time.Now().Add(10 * time.Second))
+	assert.NoError(t, err)
+
+	for {
+		_, response, err := conn.ReadMessage()
+		if err != nil {
+			t.Error("No rejection received", err)
+			return
+		}
+
+		var rejection RoadRunnerError
+		if json.Unmarshal(response, &rejection) == nil && rejection.Type != "" {
+			assertion(rejection, t)
+			return
+		}
+	}
+}
+
 func (suite *RoadRunnerTestSuite) SetupTest() {
 	suite.T().Parallel()
 }
@@ -0,0 +1,154 @@
+package main_test
+
+import (
+	"encoding/json"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+)
+
+func (suite *RoadRunnerTestSuite) TestEnvironment() {
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "StandardOutput" {
+			assert.Equal(t, "hello None", response.PipeValue)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.Equal(t, "exit status: 0", response.Value.ExitStatus)
+		}
+	}
+
+	testHeader(suite, []byte(`{
+		"language": "python",
+		"source": "import os\nprint(os.environ['GREETING'], os.environ.get('PORT'))",
+		"nonce": "environment",
+		"env": {"GREETING": "hello"}
+	}`), assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestEnvironmentRefused() {
+	for _, key := range []string{"PATH", "LD_PRELOAD", "GCONV_PATH", "A=B"} {
+		var assertionFunction = func(rejection RoadRunnerError, t *testing.T) {
+			assert.Equal(t, "ParseInput", rejection.Type)
+			assert.Contains(t, string(rejection.Value), key)
+		}
+
+		testRejection(suite, []byte(`{
+			"language": "python",
+			"source": "print(1)",
+			"nonce": "environment-refused",
+			"env": {"`+key+`": "/tmp/x"}
+		}`), assertionFunction)
+	}
+}
+
+func (suite *RoadRunnerTestSuite) TestArgumentArray() {
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "StandardOutput" {
+			assert.Equal(t, `["a b", "", "'c'"]`, response.PipeValue)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.Equal(t, "exit status: 0", response.Value.ExitStatus)
+		}
+	}
+
+	testHeader(suite, []byte(`{
+		"language": "python",
+		"source": "import sys, json\nprint(json.dumps(sys.argv[1:]))",
+		"nonce": "argument-array",
+		"commandline_arguments": ["a b", "", "'c'"]
+	}`), assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestArgumentLine() {
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "StandardOutput" {
+			assert.Equal(t, `["a", "b c", "d e"]`, response.PipeValue)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.Equal(t, "exit status: 0", response.Value.ExitStatus)
+		}
+	}
+
+	testHeader(suite, []byte(`{
+		"language": "python",
+		"source": "import sys, json\nprint(json.dumps(sys.argv[1:]))",
+		"nonce": "argument-line",
+		"commandline_arguments": "a 'b c' \"d e\""
+	}`), assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestArgumentLineUnclosed() {
+	var assertionFunction = func(rejection RoadRunnerError, t *testing.T) {
+		assert.Equal(t, "ParseInput", rejection.Type)
+		assert.Contains(t, string(rejection.Value), "commandline_arguments")
+	}
+
+	testRejection(suite, []byte(`{
+		"language": "python",
+		"source": "print(1)",
+		"nonce": "argument-unclosed",
+		"commandline_arguments": "a 'b"
+	}`), assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestLanguageAlias() {
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "StandardOutput" {
+			assert.Equal(t, "aliased", response.PipeValue)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.Equal(t, "exit status: 0", response.Value.ExitStatus)
+		}
+	}
+
+	testHeader(suite, []byte(`{
+		"language": "Python3",
+		"source": "print('aliased')",
+		"nonce": "language-alias"
+	}`), assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestLanguageFromEntrypoint() {
+	var assertionFunction = func(response RoadRunnerResponse, t *testing.T) {
+		if response.TerminalType == "StandardOutput" {
+			assert.Equal(t, "inferred", response.PipeValue)
+		}
+
+		if response.TerminalType == "EndOfOutput" {
+			assert.Equal(t, "exit status: 0", response.Value.ExitStatus)
+		}
+	}
+
+	testHeader(suite, []byte(`{
+		"files": {"app/run.py": "print('inferred')"},
+		"entrypoint": "app/run.py",
+		"nonce": "language-entrypoint"
+	}`), assertionFunction)
+}
+
+func (suite *RoadRunnerTestSuite) TestLanguageUnsupported() {
+	var assertionFunction = func(rejection RoadRunnerError, t *testing.T) {
+		assert.Equal(t, "UnsupportedLanguage", rejection.Type)
+
+		var value struct {
+			Nonce    string   `json:"nonce"`
+			Language string   `json:"language"`
+			Valid    []string `json:"valid"`
+		}
+		assert.NoError(t, json.Unmarshal(rejection.Value, &value))
+
+		assert.Equal(t, "language-unsupported", value.Nonce)
+		assert.Equal(t, "cobol", value.Language)
+		assert.Contains(t, value.Valid, "python")
+	}
+
+	testRejection(suite, []byte(`{
+		"language": "cobol",
+		"source": "DISPLAY 'HELLO'.",
+		"nonce": "language-unsupported"
+	}`), assertionFunction)
+}